@@ -1,7 +1,10 @@
 extern crate gcc;
 
 use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::Path;
+use std::process;
 
 use gcc::Config;
 
@@ -20,6 +23,7 @@ fn main() {
     let hde = match arch {
         "i686"   => "HDE/hde32.c",
         "x86_64" => "HDE/hde64.c",
+        "aarch64" | "arm64ec" | "thumbv7a" => unsupported_arm(arch),
         _        => panic!("Architecture '{}' not supported.", arch)
     };
 
@@ -32,5 +36,45 @@ fn main() {
            .file(src_dir.join(hde))
            .compile("libminhook.a");
 
+    let version = minhook_version(&Path::new(&root_dir).join("src/minhook/README.md"));
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut version_file = File::create(Path::new(&out_dir).join("version.rs")).unwrap();
+    writeln!(version_file, "const MINHOOK_VERSION: &'static str = {:?};", version).unwrap();
+    writeln!(version_file, "const MINHOOK_ARCH: &'static str = {:?};", arch).unwrap();
+
     println!("cargo:rerun-if-changed=src/minhook/src/");
+    println!("cargo:rerun-if-changed=src/minhook/README.md");
+}
+
+// MinHook only contains an x86/x64 disassembler and trampoline generator, so there is nothing
+// that could be compiled for ARM targets.
+fn unsupported_arm(arch: &str) -> ! {
+    println!("cargo:warning=MinHook does not support the '{}' architecture.", arch);
+    println!("cargo:warning=Build for an x86 or x64 Windows target instead (for example \
+              'x86_64-pc-windows-msvc'), which can run under emulation on Windows on ARM.");
+
+    let mut stderr = io::stderr();
+    let _ = writeln!(stderr, "error: MinHook does not support the '{}' architecture. Supported \
+                              architectures are 'i686' and 'x86_64'.", arch);
+    process::exit(1);
+}
+
+// Finds the most recent version in the version history of MinHook's readme.
+fn minhook_version(readme: &Path) -> String {
+    let readme = match File::open(readme) {
+        Ok(readme) => BufReader::new(readme),
+        Err(_) => return String::from("unknown")
+    };
+
+    readme.lines()
+          .filter_map(|line| line.ok())
+          .skip_while(|line| !line.contains("Version history"))
+          .filter_map(|line| {
+              line.split_whitespace()
+                  .map(|word| word.trim_left_matches(|c: char| !c.is_alphanumeric()))
+                  .find(|word| word.starts_with('v') && word[1..].starts_with(|c: char| c.is_digit(10)))
+                  .map(String::from)
+          })
+          .next()
+          .unwrap_or_else(|| String::from("unknown"))
 }
\ No newline at end of file
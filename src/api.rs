@@ -0,0 +1,165 @@
+use std::ptr;
+
+use winapi;
+
+use ffi;
+use function::FnPointer;
+use super::{Result, s2r};
+
+
+
+// The MinHook functions used by this library. Going through this trait instead of calling the
+// functions in `ffi` directly allows the logic on top of them to be tested without patching any
+// code.
+pub trait MinHookApi: Sync {
+    unsafe fn create(&self, target: FnPointer, detour: FnPointer) -> Result<FnPointer>;
+
+    // Returns the trampoline and the target.
+    unsafe fn create_api(&self, module: winapi::LPCWSTR, function: winapi::LPCSTR, detour: FnPointer) -> Result<(FnPointer, FnPointer)>;
+
+    fn remove(&self, target: FnPointer) -> Result<()>;
+
+    fn enable(&self, target: FnPointer) -> Result<()>;
+
+    fn disable(&self, target: FnPointer) -> Result<()>;
+
+    fn queue_enable(&self, target: FnPointer) -> Result<()>;
+
+    fn queue_disable(&self, target: FnPointer) -> Result<()>;
+
+    fn apply_queued(&self) -> Result<()>;
+
+    fn uninitialize(&self) -> Result<()>;
+}
+
+
+
+// Calls the actual MinHook library.
+//
+// The out-parameters are initialized to null before they are passed to MinHook, and they are only
+// turned into function pointers after MinHook reported success.
+pub struct RealApi;
+
+impl MinHookApi for RealApi {
+    unsafe fn create(&self, target: FnPointer, detour: FnPointer) -> Result<FnPointer> {
+        let mut trampoline = ptr::null_mut();
+        try!(s2r(ffi::MH_CreateHook(target.to_raw(), detour.to_raw(), &mut trampoline)));
+        Ok(FnPointer::from_raw(trampoline))
+    }
+
+    unsafe fn create_api(&self, module: winapi::LPCWSTR, function: winapi::LPCSTR, detour: FnPointer) -> Result<(FnPointer, FnPointer)> {
+        let mut trampoline = ptr::null_mut();
+        let mut target = ptr::null_mut();
+        try!(s2r(ffi::MH_CreateHookApiEx(module, function, detour.to_raw(), &mut trampoline, &mut target)));
+        Ok((FnPointer::from_raw(trampoline), FnPointer::from_raw(target)))
+    }
+
+    fn remove(&self, target: FnPointer) -> Result<()> {
+        unsafe { s2r(ffi::MH_RemoveHook(target.to_raw())) }
+    }
+
+    fn enable(&self, target: FnPointer) -> Result<()> {
+        unsafe { s2r(ffi::MH_EnableHook(target.to_raw())) }
+    }
+
+    fn disable(&self, target: FnPointer) -> Result<()> {
+        unsafe { s2r(ffi::MH_DisableHook(target.to_raw())) }
+    }
+
+    fn queue_enable(&self, target: FnPointer) -> Result<()> {
+        unsafe { s2r(ffi::MH_QueueEnableHook(target.to_raw())) }
+    }
+
+    fn queue_disable(&self, target: FnPointer) -> Result<()> {
+        unsafe { s2r(ffi::MH_QueueDisableHook(target.to_raw())) }
+    }
+
+    fn apply_queued(&self) -> Result<()> {
+        unsafe { s2r(ffi::MH_ApplyQueued()) }
+    }
+
+    fn uninitialize(&self) -> Result<()> {
+        unsafe { s2r(ffi::MH_Uninitialize()) }
+    }
+}
+
+
+
+// Records all calls instead of hooking anything.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockApi {
+    pub calls: ::std::sync::Mutex<Vec<(&'static str, Option<FnPointer>)>>,
+    // Returned by `create()` one by one, before it succeeds.
+    pub fail_create: ::std::sync::Mutex<Vec<::Error>>,
+    // Targets for which `queue_enable()` and `queue_disable()` fail with `NotCreated`.
+    pub not_created: Vec<FnPointer>,
+    pub fail_apply: Option<::Error>
+}
+
+#[cfg(test)]
+impl MockApi {
+    pub fn calls(&self) -> Vec<(&'static str, Option<FnPointer>)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, name: &'static str, target: Option<FnPointer>) -> Result<()> {
+        self.calls.lock().unwrap().push((name, target));
+        Ok(())
+    }
+
+    fn check_created(&self, target: FnPointer) -> Result<()> {
+        if self.not_created.contains(&target) {
+            return Err(::Error::NotCreated);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl MinHookApi for MockApi {
+    unsafe fn create(&self, target: FnPointer, _detour: FnPointer) -> Result<FnPointer> {
+        try!(self.record("create", Some(target)));
+        let mut failures = self.fail_create.lock().unwrap();
+        if !failures.is_empty() {
+            return Err(failures.remove(0));
+        }
+        Ok(target)
+    }
+
+    unsafe fn create_api(&self, _module: winapi::LPCWSTR, _function: winapi::LPCSTR, _detour: FnPointer) -> Result<(FnPointer, FnPointer)> {
+        try!(self.record("create_api", None));
+        Err(::Error::FunctionNotFound)
+    }
+
+    fn remove(&self, target: FnPointer) -> Result<()> {
+        self.record("remove", Some(target))
+    }
+
+    fn enable(&self, target: FnPointer) -> Result<()> {
+        self.record("enable", Some(target))
+    }
+
+    fn disable(&self, target: FnPointer) -> Result<()> {
+        self.record("disable", Some(target))
+    }
+
+    fn queue_enable(&self, target: FnPointer) -> Result<()> {
+        try!(self.record("queue_enable", Some(target)));
+        self.check_created(target)
+    }
+
+    fn queue_disable(&self, target: FnPointer) -> Result<()> {
+        try!(self.record("queue_disable", Some(target)));
+        self.check_created(target)
+    }
+
+    fn apply_queued(&self) -> Result<()> {
+        try!(self.record("apply_queued", None));
+        self.fail_apply.map_or(Ok(()), Err)
+    }
+
+    fn uninitialize(&self) -> Result<()> {
+        self.record("uninitialize", None)
+    }
+}
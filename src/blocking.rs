@@ -0,0 +1,72 @@
+//! Detours that block on futures.
+//!
+//! This module is only available if the `futures` feature is enabled.
+//!
+//! A detour function is called synchronously by the caller of the target function and has to
+//! return a value of the target's return type before the caller can continue. A detour function
+//! can still hand work off to some asynchronous runtime by blocking the calling thread until the
+//! resulting future completes, using `block_on_detour()`. The `static_hooks!` macro supports this
+//! directly using the `block_on(EXECUTOR)` modifier, in which case the detour `Fn` returns a
+//! future of the target's return type:
+//!
+//! ```ignore
+//! static_hooks! {
+//!     impl HOOK for target: fn(i32) -> i32 = block_on(Wait) |x| futures::finished::<_, ()>(x + 1);
+//! }
+//! ```
+//!
+//! # Deadlocks
+//!
+//! Blocking inside of a detour function deadlocks whenever the future can not complete without
+//! the blocked thread. In particular:
+//!
+//! * The future must not be driven by an executor that runs on the thread that calls the target
+//!   function, such as a single-threaded event loop that called the target function itself.
+//! * The future must not call the target function from another thread and wait for that call
+//!   while the hook is enabled, since every such call blocks again. This also applies to worker
+//!   threads of a thread pool, which may run out of threads.
+//! * The future must not wait for any lock that the caller of the target function may hold. Most
+//!   notably, if the target function can be called while the loader lock is held (for example
+//!   from `DllMain`), the future must not start any threads or load any libraries.
+
+use std::fmt;
+
+use futures::{Future, IntoFuture};
+
+
+
+/// An executor that can run a future to completion on behalf of a blocked detour function.
+pub trait Executor: Sync {
+    /// Runs the given future to completion, blocking the current thread until it completes.
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Item, F::Error>;
+}
+
+/// An executor that polls the future on the thread of the detour function itself.
+///
+/// The future is driven by `Future::wait()`, so any work that it hands off to other threads
+/// resumes the detour function when it completes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Wait;
+
+impl Executor for Wait {
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Item, F::Error> {
+        future.wait()
+    }
+}
+
+/// Runs the given future to completion using the given executor and returns its value.
+///
+/// This is meant to be called from a detour function that has to return the value of an
+/// asynchronous computation. See the module documentation for the risk of deadlocks.
+///
+/// # Panics
+///
+/// Panics if the future fails. Such a panic is handled just like any other panic inside of the
+/// detour function.
+pub fn block_on_detour<E, F>(executor: &E, future: F) -> F::Item
+where E: Executor + ?Sized, F: IntoFuture, F::Error: fmt::Debug {
+    match executor.block_on(future.into_future()) {
+        Ok(value) => value,
+        Err(error) => panic!("detour future failed: {:?}", error)
+    }
+}
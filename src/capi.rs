@@ -0,0 +1,74 @@
+//! A C interface for creating and managing hooks.
+//!
+//! This module is only available if the `capi` feature is enabled. It allows hooks to be
+//! installed from C or C++ code that links against this crate. There is no type checking of any
+//! kind; the functions are thin wrappers around the raw MinHook functions that make sure MinHook
+//! is initialized.
+//!
+//! All functions return a MinHook status code, with the same values as the `MH_STATUS` enum in
+//! `MinHook.h`. `MH_OK` (0) indicates success.
+//!
+//! Hooks created through this module are not known to the rest of this library. They are not
+//! returned by `all_hooks()` or counted by `enabled_hook_count()`, and `uninitialize()` does not
+//! fail with `HooksAlive` because of them. Uninitializing MinHook removes them and frees their
+//! trampolines, so it must not be done while a C caller still uses any of them.
+
+use std::os::raw::c_void;
+
+use ffi::{self, MH_STATUS};
+use super::{Error, initialize};
+
+
+
+/// Creates a disabled hook for the given target function, storing a pointer to the trampoline
+/// function in `out_trampoline`, which may be null.
+///
+/// # Safety
+///
+/// The detour function must have the same signature as the target function.
+#[no_mangle]
+pub unsafe extern "C" fn minhook_create(target: *mut c_void, detour: *mut c_void, out_trampoline: *mut *mut c_void) -> i32 {
+    if let Err(error) = initialize() {
+        return to_status(error) as i32;
+    }
+    ffi::MH_CreateHook(target, detour, out_trampoline) as i32
+}
+
+/// Enables the hook for the given target function.
+///
+/// # Safety
+///
+/// See `minhook_create()`.
+#[no_mangle]
+pub unsafe extern "C" fn minhook_enable(target: *mut c_void) -> i32 {
+    ffi::MH_EnableHook(target) as i32
+}
+
+/// Disables the hook for the given target function.
+///
+/// # Safety
+///
+/// See `minhook_create()`.
+#[no_mangle]
+pub unsafe extern "C" fn minhook_disable(target: *mut c_void) -> i32 {
+    ffi::MH_DisableHook(target) as i32
+}
+
+/// Removes the hook for the given target function.
+///
+/// # Safety
+///
+/// The trampoline function becomes invalid.
+#[no_mangle]
+pub unsafe extern "C" fn minhook_remove(target: *mut c_void) -> i32 {
+    ffi::MH_RemoveHook(target) as i32
+}
+
+// `initialize()` only fails with errors that come from MinHook.
+fn to_status(error: Error) -> MH_STATUS {
+    match error {
+        Error::NotInitialized => MH_STATUS::MH_ERROR_NOT_INITIALIZED,
+        Error::MemoryAlloc => MH_STATUS::MH_ERROR_MEMORY_ALLOC,
+        _ => MH_STATUS::MH_UNKNOWN
+    }
+}
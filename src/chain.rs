@@ -0,0 +1,243 @@
+use std::mem;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel32;
+
+use function::Function;
+use super::{Hook, HookResult, StaticHook};
+
+
+
+// Links are called concurrently from any thread that calls the target function, and they are
+// dropped by whichever thread drops the last reference to them, so they have to be both `Send`
+// and `Sync`. Because of this, `HookChain`, `ChainLink` and the dispatcher are `Send` and `Sync`
+// without any unsafe implementations.
+type Link<T> = Fn(<T as Function>::Args, Next<T>) -> <T as Function>::Output + Send + Sync;
+
+fn link<T, F>(detour: F) -> Arc<Link<T>>
+where T: Function, F: Fn(T::Args, Next<T>) -> <T as Function>::Output + Send + Sync + 'static {
+    Arc::new(detour)
+}
+
+type ThreadFilter = Fn(u32) -> bool + Send + Sync;
+
+struct ChainState<T: Function> {
+    // The list is replaced as a whole on every change, so that calls in progress can keep using
+    // the old list without holding the lock.
+    links: RwLock<Arc<Vec<(usize, Arc<Link<T>>)>>>,
+    next_id: AtomicUsize,
+    filter: RwLock<Option<Arc<ThreadFilter>>>
+}
+
+
+
+/// A chain of detour closures sharing a single hook.
+///
+/// Every call to the target function passes through all the closures in the chain in the order
+/// in which they were pushed. Each closure receives the arguments as a tuple together with a
+/// `Next` value, which can be used to pass (possibly modified) arguments on to the next closure
+/// in the chain. The last closure passes them on to the original function.
+///
+/// A chain is built on top of an uninitialized `StaticHook` without a default detour. Only
+/// chains for safe function types are supported.
+pub struct HookChain<T: Function> {
+    hook: &'static StaticHook<T>,
+    state: Arc<ChainState<T>>
+}
+
+impl<T> HookChain<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    /// Initializes and installs the given static hook with a dispatching detour, returning the
+    /// new, initially empty, chain.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hook was already initialized.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for `StaticHook::initialize()`.
+    pub unsafe fn new(hook: &'static StaticHook<T>) -> HookResult<HookChain<T>> {
+        let state = Arc::new(ChainState {
+            links: RwLock::new(Arc::new(Vec::new())),
+            next_id: AtomicUsize::new(0),
+            filter: RwLock::new(None)
+        });
+
+        try!(hook.initialize(Dispatcher {
+            hook: hook,
+            state: state.clone()
+        }));
+
+        Ok(HookChain {
+            hook: hook,
+            state: state
+        })
+    }
+
+    /// Appends a detour closure to the end of the chain.
+    ///
+    /// The closure is removed from the chain again when the returned handle is dropped.
+    pub fn push<F>(&self, detour: F) -> ChainLink<T>
+    where F: Fn(T::Args, Next<T>) -> <T as Function>::Output + Send + Sync + 'static {
+        let id = self.state.next_id.fetch_add(1, Ordering::SeqCst);
+        let detour = link::<T, _>(detour);
+
+        let mut links = self.state.links.write().unwrap();
+        let mut new_links = (**links).clone();
+        new_links.push((id, detour));
+        *links = Arc::new(new_links);
+
+        ChainLink {
+            state: self.state.clone(),
+            id: id
+        }
+    }
+
+    /// Restricts the chain to the threads for which the given filter returns `true`.
+    ///
+    /// The filter receives the identifier of the calling thread, as returned by
+    /// `GetCurrentThreadId`, on every call to the target function. Calls from threads that are
+    /// excluded bypass all the detour closures and go straight to the original function. This
+    /// replaces any previously set filter.
+    pub fn set_thread_filter<F>(&self, filter: F)
+    where F: Fn(u32) -> bool + Send + Sync + 'static {
+        *self.state.filter.write().unwrap() = Some(Arc::new(filter));
+    }
+
+    /// Removes the thread filter, so that calls from all threads pass through the chain again.
+    pub fn clear_thread_filter(&self) {
+        *self.state.filter.write().unwrap() = None;
+    }
+
+    /// Returns the number of detour closures in this chain.
+    pub fn len(&self) -> usize {
+        self.state.links.read().unwrap().len()
+    }
+
+    /// Returns `true` if there are no detour closures in this chain.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Function> Deref for HookChain<T> {
+    type Target = Hook<T>;
+
+    fn deref(&self) -> &Hook<T> {
+        &**self.hook
+    }
+}
+
+
+
+/// A handle to a detour closure in a `HookChain`.
+///
+/// The closure is removed from the chain when this handle is dropped.
+#[must_use]
+pub struct ChainLink<T: Function> {
+    state: Arc<ChainState<T>>,
+    id: usize
+}
+
+impl<T> ChainLink<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    /// Replaces the detour closure with a closure that wraps it.
+    ///
+    /// The given closure receives the arguments, the `Next` value and the old detour closure,
+    /// which it can call in order to invoke the old behavior. The detour closure keeps its
+    /// position in the chain.
+    pub fn map_detour<F>(self, f: F) -> ChainLink<T>
+    where F: Fn(T::Args, Next<T>, &(Fn(T::Args, Next<T>) -> <T as Function>::Output + Send + Sync)) -> <T as Function>::Output
+           + Send + Sync + 'static {
+        {
+            let mut links = self.state.links.write().unwrap();
+            let mut new_links = (**links).clone();
+            if let Some(&mut (_, ref mut detour)) = new_links.iter_mut().find(|&&mut (id, _)| id == self.id) {
+                let old = detour.clone();
+                *detour = link::<T, _>(move |args, next| f(args, next, &*old));
+            }
+            *links = Arc::new(new_links);
+        }
+        self
+    }
+
+    /// Removes the detour closure from the chain.
+    pub fn remove(self) {
+        mem::drop(self);
+    }
+}
+
+impl<T: Function> Drop for ChainLink<T> {
+    fn drop(&mut self) {
+        let mut links = self.state.links.write().unwrap();
+        let new_links = links.iter().filter(|&&(id, _)| id != self.id).cloned().collect();
+        *links = Arc::new(new_links);
+    }
+}
+
+
+
+/// The remainder of a `HookChain` as seen from one of its detour closures.
+pub struct Next<'a, T: Function + 'a> {
+    links: &'a [(usize, Arc<Link<T>>)],
+    trampoline: T
+}
+
+impl<'a, T> Next<'a, T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    /// Passes the given arguments on to the next detour closure in the chain, or to the original
+    /// function if this is the end of the chain.
+    pub fn call(self, args: T::Args) -> <T as Function>::Output {
+        match self.links.split_first() {
+            Some((&(_, ref detour), rest)) => detour(args, Next {
+                links: rest,
+                trampoline: self.trampoline
+            }),
+            None => self.trampoline.call(args)
+        }
+    }
+}
+
+
+
+struct Dispatcher<T: Function> {
+    hook: &'static StaticHook<T>,
+    state: Arc<ChainState<T>>
+}
+
+impl<T> Fn<<T as Function>::Args> for Dispatcher<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call(&self, args: T::Args) -> <T as Function>::Output {
+        let filter = self.state.filter.read().unwrap().clone();
+        if let Some(filter) = filter {
+            if !filter(unsafe { kernel32::GetCurrentThreadId() }) {
+                return self.hook.trampoline().call(args);
+            }
+        }
+
+        let links = self.state.links.read().unwrap().clone();
+        Next {
+            links: &links,
+            trampoline: self.hook.trampoline()
+        }.call(args)
+    }
+}
+
+impl<T> FnMut<<T as Function>::Args> for Dispatcher<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call_mut(&mut self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&*self, args)
+    }
+}
+
+impl<T> FnOnce<<T as Function>::Args> for Dispatcher<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    type Output = <T as Function>::Output;
+
+    extern "rust-call" fn call_once(self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&self, args)
+    }
+}
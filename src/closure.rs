@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+
+use kernel32;
+use winapi;
+
+use function::{FnPointer, Function};
+use patch;
+use sync::StaticRwCell;
+use super::{Error, Hook, HookError, HookResult, Result};
+
+
+
+// The closures of all closure hooks, by the thunk of the hook. Every hook has its own thunk, which
+// stores its own address in a TLS slot and jumps to the detour function for the closure type. The
+// detour function looks up the closure here using that address.
+static CLOSURES: StaticRwCell<Option<Vec<(FnPointer, Box<Any + Send + Sync>)>>> = StaticRwCell::new(None);
+
+// The TLS slot used by the thunks plus one, or zero if it is not allocated yet.
+static SLOT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+const TLS_OUT_OF_INDEXES: winapi::DWORD = 0xffff_ffff;
+// Only the first TLS slots are stored in the thread environment block itself, where the thunks
+// can write to them.
+const TLS_MINIMUM_AVAILABLE: winapi::DWORD = 64;
+
+// Returns the TLS slot used by the thunks, allocating it first if necessary. The slot is never
+// freed.
+fn slot() -> Result<winapi::DWORD> {
+    let slot = SLOT.load(Ordering::SeqCst);
+    if slot != 0 {
+        return Ok(slot as winapi::DWORD - 1);
+    }
+
+    let index = unsafe { kernel32::TlsAlloc() };
+    if index == TLS_OUT_OF_INDEXES {
+        return Err(Error::MemoryAlloc);
+    }
+    if index >= TLS_MINIMUM_AVAILABLE {
+        unsafe { kernel32::TlsFree(index); }
+        return Err(Error::MemoryAlloc);
+    }
+
+    // Another thread may have allocated a slot in the meantime.
+    match SLOT.compare_and_swap(0, index as usize + 1, Ordering::SeqCst) {
+        0 => Ok(index),
+        slot => {
+            unsafe { kernel32::TlsFree(index); }
+            Ok(slot as winapi::DWORD - 1)
+        }
+    }
+}
+
+unsafe fn alloc_thunk(detour: FnPointer) -> Result<FnPointer> {
+    let slot = try!(slot());
+    let thunk = kernel32::VirtualAlloc(ptr::null_mut(), patch::THUNK_SIZE as winapi::SIZE_T,
+                                       winapi::MEM_COMMIT | winapi::MEM_RESERVE, winapi::PAGE_READWRITE);
+    if thunk.is_null() {
+        return Err(Error::MemoryAlloc);
+    }
+
+    patch::write_thunk(thunk as *mut u8, slot, thunk as usize, detour);
+    let mut protect = 0;
+    if kernel32::VirtualProtect(thunk, patch::THUNK_SIZE as winapi::SIZE_T, winapi::PAGE_EXECUTE_READ, &mut protect) == 0 {
+        kernel32::VirtualFree(thunk, 0, winapi::MEM_RELEASE);
+        return Err(Error::MemoryProtect);
+    }
+    kernel32::FlushInstructionCache(kernel32::GetCurrentProcess(), thunk as winapi::LPCVOID,
+                                    patch::THUNK_SIZE as winapi::SIZE_T);
+    Ok(FnPointer::from_raw(thunk))
+}
+
+unsafe fn free_thunk(thunk: FnPointer) {
+    kernel32::VirtualFree(thunk.to_raw(), 0, winapi::MEM_RELEASE);
+}
+
+fn register<F: Send + Sync + 'static>(thunk: FnPointer, closure: F) {
+    let entry: (FnPointer, Box<Any + Send + Sync>) = (thunk, Box::new(Arc::new(closure)));
+    CLOSURES.with_mut(|closures| {
+        if let Some(ref mut list) = *closures {
+            list.push(entry);
+            return;
+        }
+        *closures = Some(vec![entry]);
+    });
+}
+
+fn unregister(thunk: FnPointer) {
+    CLOSURES.with_mut(|closures| {
+        if let Some(ref mut closures) = *closures {
+            closures.retain(|&(id, _)| id != thunk);
+        }
+    });
+}
+
+// Returns the thunk that last jumped to a detour function on the current thread. `TlsGetValue()`
+// resets the last error, which the caller of the target function may still depend on, so it is
+// restored afterwards.
+unsafe fn calling_thunk() -> FnPointer {
+    let error = kernel32::GetLastError();
+    let thunk = kernel32::TlsGetValue(SLOT.load(Ordering::SeqCst) as winapi::DWORD - 1);
+    kernel32::SetLastError(error);
+    FnPointer::from_raw(thunk)
+}
+
+// Called by the detour function for the closure type `F`.
+//
+// The closure is cloned out of the list, so that the lock is not held while it runs. Panics are
+// handled like panics in the detour closure of a static hook, since they must not unwind into
+// the caller of the target function.
+pub fn call_closure<F, A, R>(args: A) -> R
+where F: Fn<A, Output = R> + Send + Sync + 'static {
+    // Nothing may call another closure hook on this thread before the slot is read.
+    let thunk = unsafe { calling_thunk() };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(move || {
+        let closure = CLOSURES.with(|closures| {
+            closures.as_ref()
+                    .and_then(|closures| closures.iter().find(|&&(id, _)| id == thunk))
+                    .and_then(|&(_, ref closure)| closure.downcast_ref::<Arc<F>>())
+                    .cloned()
+        }).expect("detour of closure hook called after the hook was destroyed");
+
+        Fn::call(&*closure, args)
+    }));
+    match result {
+        Ok(output) => output,
+        Err(payload) => ::panic::__handle(module_path!(), "closure_hook", payload)
+    }
+}
+
+
+
+pub unsafe fn create<T, F>(target: T, closure: F) -> HookResult<ClosureHook<T>>
+where T: Function, F: Fn<T::Args, Output = T::Output> + Send + Sync + 'static {
+    let thunk = try!(alloc_thunk(T::__closure_detour::<F>().to_ptr())
+                         .map_err(|error| HookError::new(error, Some(target.to_ptr()), "create closure hook")));
+    register(thunk, closure);
+
+    match Hook::create_with_ptr_detour(target, thunk) {
+        Ok(hook) => Ok(ClosureHook {
+            hook: Some(hook),
+            thunk: thunk
+        }),
+        Err(error) => {
+            unregister(thunk);
+            free_thunk(thunk);
+            Err(error)
+        }
+    }
+}
+
+
+
+/// A hook with a detour closure that is not bound to a static.
+///
+/// This hook is created by `Hook::create_closure()`. The closure is dropped when the hook is
+/// destroyed.
+pub struct ClosureHook<T: Function> {
+    hook: Option<Hook<T>>,
+    thunk: FnPointer
+}
+
+impl<T: Function> Deref for ClosureHook<T> {
+    type Target = Hook<T>;
+
+    fn deref(&self) -> &Hook<T> {
+        self.hook.as_ref().unwrap()
+    }
+}
+
+impl<T: Function> Drop for ClosureHook<T> {
+    fn drop(&mut self) {
+        // The hook has to be removed before the closure and the thunk, since the detour function
+        // might still be called until then.
+        self.hook.take();
+        unregister(self.thunk);
+        unsafe { free_thunk(self.thunk); }
+    }
+}
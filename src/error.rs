@@ -1,7 +1,8 @@
-use std::error;
+use std::{error, io};
 use std::fmt::{self, Display, Formatter};
 
 use ffi::MH_STATUS;
+use function::FnPointer;
 
 
 
@@ -26,9 +27,31 @@ pub enum Error {
     /// The specified pointer is invalid. It points the address of non-allocated
     /// and/or non-executable region.
     NotExecutable,
+    /// The specified pointer points to a region that is not executable. Contains the protection
+    /// flags of the region, or 0 if the region is not committed.
+    NotExecutableDetail {
+        /// The `PAGE_*` protection flags of the region.
+        protect: u32
+    },
     /// The specified target function cannot be hooked.
     UnsupportedFunction,
+    /// The specified target function returns or jumps away before there is room for the jump
+    /// that MinHook patches it with, and it cannot be hot-patched.
+    FunctionTooSmall {
+        /// The number of bytes before the function returns or jumps away.
+        size: u32
+    },
+    /// The instructions at the start of the specified target function could not be decoded.
+    InvalidInstruction {
+        /// The offset of the instruction from the start of the function.
+        offset: u32
+    },
     /// Failed to allocate memory.
+    ///
+    /// On x64, MinHook allocates the trampoline of a hook within 1 GB of the target function, so
+    /// that it can be reached using relative jumps. This error then usually means that the
+    /// address space near the target function is exhausted or too fragmented. MinHook does not
+    /// support allocating trampolines further away.
     MemoryAlloc,
     /// Failed to change the memory protection.
     MemoryProtect,
@@ -36,11 +59,27 @@ pub enum Error {
     ModuleNotFound,
     /// The specified function is not found.
     FunctionNotFound,
+    /// The specified ordinal value is not exported by the module, because it lies outside of the
+    /// range of ordinals in its export table.
+    OrdinalOutOfRange {
+        /// The specified ordinal value.
+        ordinal: u16,
+        /// The first ordinal value exported by the module.
+        base: u32,
+        /// The number of entries in the export table.
+        count: u32
+    },
 
     /// The specified module name is invalid.
     InvalidModuleName,
     /// The specified function name is invalid.
-    InvalidFunctionName
+    InvalidFunctionName,
+    /// The specified module name or function name contains a NUL character.
+    InteriorNul,
+    /// There are still hooks alive.
+    HooksAlive,
+    /// The target function and the detour function are the same function.
+    TargetIsDetour
 }
 
 impl Error {
@@ -63,6 +102,51 @@ impl Error {
             MH_STATUS::MH_UNKNOWN => unreachable!(),
         }
     }
+
+    /// Returns `true` if the error indicates that MinHook or the hook is already in the requested
+    /// state.
+    pub fn is_already_state(&self) -> bool {
+        match *self {
+            Error::AlreadyInitialized |
+            Error::AlreadyCreated |
+            Error::AlreadyEnabled |
+            Error::Disabled => true,
+            _ => false
+        }
+    }
+
+    /// Returns `true` if the error indicates that the target module or function could not be
+    /// found.
+    pub fn is_not_found(&self) -> bool {
+        match *self {
+            Error::ModuleNotFound |
+            Error::FunctionNotFound |
+            Error::OrdinalOutOfRange { .. } => true,
+            _ => false
+        }
+    }
+
+    /// Returns `true` if the error indicates that the target function itself can not be hooked.
+    pub fn is_target_unhookable(&self) -> bool {
+        match *self {
+            Error::NotExecutable |
+            Error::NotExecutableDetail { .. } |
+            Error::UnsupportedFunction |
+            Error::FunctionTooSmall { .. } |
+            Error::InvalidInstruction { .. } => true,
+            _ => false
+        }
+    }
+
+    /// Returns `true` if the error is caused by a memory operation that might succeed when
+    /// retried later.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            Error::MemoryAlloc |
+            Error::MemoryProtect => true,
+            _ => false
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -75,21 +159,35 @@ impl error::Error for Error {
             Error::AlreadyEnabled => "hook already enabled",
             Error::Disabled => "hook not enabled",
             Error::NotExecutable => "invalid pointer",
+            Error::NotExecutableDetail { .. } => "pointer to non-executable region",
             Error::UnsupportedFunction => "function cannot be hooked",
+            Error::FunctionTooSmall { .. } => "function too small to be hooked",
+            Error::InvalidInstruction { .. } => "invalid instruction in function",
             Error::MemoryAlloc => "failed to allocate memory",
             Error::MemoryProtect => "failed to change the memory protection",
             Error::ModuleNotFound => "module not loaded",
             Error::FunctionNotFound => "function not found",
+            Error::OrdinalOutOfRange { .. } => "ordinal out of range",
 
             Error::InvalidModuleName => "invalid module name",
-            Error::InvalidFunctionName => "invalid function name"
+            Error::InvalidFunctionName => "invalid function name",
+            Error::InteriorNul => "name contains NUL character",
+            Error::HooksAlive => "hooks still alive",
+            Error::TargetIsDetour => "target is detour"
         }
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+const MEMORY_ALLOC_MESSAGE: &'static str = "Failed to allocate memory; the trampoline has to be \
+                                            allocated within 1 GB of the target function, so \
+                                            the address space near it is likely exhausted";
+#[cfg(not(target_arch = "x86_64"))]
+const MEMORY_ALLOC_MESSAGE: &'static str = "Failed to allocate memory";
+
 impl Display for Error {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-         write!(formatter, "{}", match *self {
+        let message = match *self {
             Error::AlreadyInitialized => "MinHook is already initialized",
             Error::NotInitialized => "MinHook is not initialized yet, or already uninitialized",
             Error::AlreadyCreated => "The hook for the specified target function is already \
@@ -101,14 +199,116 @@ impl Display for Error {
                                 already disabled",
             Error::NotExecutable => "The specified pointer is invalid; it points the address of \
                                      non-allocated and/or non-executable region",
+            Error::NotExecutableDetail { protect } => {
+                return write!(formatter, "The specified pointer points to a region with protection \
+                                          {:#x}, which is not executable", protect);
+            }
             Error::UnsupportedFunction => "The specified target function cannot be hooked",
-            Error::MemoryAlloc => "Failed to allocate memory",
+            Error::FunctionTooSmall { size } => {
+                return write!(formatter, "The specified target function ends after {} bytes, which \
+                                          leaves no room for the 5 byte jump", size);
+            }
+            Error::InvalidInstruction { offset } => {
+                return write!(formatter, "The instruction at offset {} of the specified target \
+                                          function could not be decoded", offset);
+            }
+            Error::MemoryAlloc => MEMORY_ALLOC_MESSAGE,
             Error::MemoryProtect => "Failed to change the memory protection",
             Error::ModuleNotFound => "The specified module is not loaded",
             Error::FunctionNotFound => "The specified function is not found",
+            Error::OrdinalOutOfRange { ordinal, base, count } => {
+                return write!(formatter, "The specified ordinal {} is not exported by the module, \
+                                          which exports {} ordinals starting at {}", ordinal, count, base);
+            }
 
             Error::InvalidModuleName => "The specified module name is invalid",
-            Error::InvalidFunctionName => "The specified function name is invalid"
-        })
+            Error::InvalidFunctionName => "The specified function name is invalid",
+            Error::InteriorNul => "The specified module name or function name contains a NUL \
+                                   character",
+            Error::HooksAlive => "There are still hooks alive",
+            Error::TargetIsDetour => "The target function and the detour function are the same \
+                                      function"
+        };
+        write!(formatter, "{}", message)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        let kind = match error {
+            Error::ModuleNotFound |
+            Error::FunctionNotFound |
+            Error::OrdinalOutOfRange { .. } => io::ErrorKind::NotFound,
+            Error::InvalidModuleName |
+            Error::InvalidFunctionName |
+            Error::InteriorNul => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::Other
+        };
+        io::Error::new(kind, error)
     }
-}
\ No newline at end of file
+}
+
+
+
+/// The error type of the high-level hook operations of `Hook`, `HookQueue` and the static hooks.
+///
+/// Wraps the `Error` that caused the operation to fail, together with the operation itself and
+/// its target function, so that a failure can be traced back to a particular hook.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HookError {
+    /// The underlying error.
+    pub source: Error,
+    /// The target function of the failed operation, or `None` if it is not known.
+    pub target: Option<FnPointer>,
+    /// A short description of the failed operation, such as `"enable hook"`.
+    pub context: &'static str
+}
+
+impl HookError {
+    /// Creates a new error for the given operation.
+    pub fn new(source: Error, target: Option<FnPointer>, context: &'static str) -> HookError {
+        HookError {
+            source: source,
+            target: target,
+            context: context
+        }
+    }
+}
+
+/// Compares only the underlying error.
+impl PartialEq<Error> for HookError {
+    fn eq(&self, other: &Error) -> bool {
+        self.source == *other
+    }
+}
+
+impl error::Error for HookError {
+    fn description(&self) -> &str {
+        error::Error::description(&self.source)
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        Some(&self.source)
+    }
+}
+
+impl Display for HookError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self.target {
+            Some(target) => write!(formatter, "Failed to {} for {:p}: {}", self.context, target, self.source),
+            None => write!(formatter, "Failed to {}: {}", self.context, self.source)
+        }
+    }
+}
+
+impl From<HookError> for Error {
+    fn from(error: HookError) -> Error {
+        error.source
+    }
+}
+
+impl From<HookError> for io::Error {
+    fn from(error: HookError) -> io::Error {
+        io::Error::new(io::Error::from(error.source).kind(), error)
+    }
+}
@@ -0,0 +1,222 @@
+use std::ffi::{CStr, OsStr};
+use std::os::raw::c_char;
+use std::slice;
+
+use winapi;
+
+use function::FnPointer;
+use super::{Error, FunctionId, Result, ascii_function_name, module_handle};
+
+
+
+// Forwarders can point to other forwarders, so guard against cycles.
+const MAX_FORWARDS: usize = 16;
+
+const DOS_SIGNATURE: u16 = 0x5a4d;
+const NT_SIGNATURE: u32 = 0x4550;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+#[repr(C)]
+#[allow(dead_code)]
+struct ExportDirectory {
+    characteristics: u32,
+    time_date_stamp: u32,
+    major_version: u16,
+    minor_version: u16,
+    name: u32,
+    base: u32,
+    number_of_functions: u32,
+    number_of_names: u32,
+    address_of_functions: u32,
+    address_of_names: u32,
+    address_of_name_ordinals: u32
+}
+
+enum Symbol {
+    Ordinal(u32),
+    Name(Vec<u8>)
+}
+
+enum Target {
+    Address(FnPointer),
+    Forwarder(Vec<u8>)
+}
+
+
+
+/// A function exported by a module.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Export {
+    /// The name of the function, if it is exported by name.
+    pub name: Option<String>,
+    /// The ordinal value of the function.
+    pub ordinal: u16,
+    /// The address of the function.
+    pub address: FnPointer
+}
+
+
+
+pub fn resolve_forwarded(module: &OsStr, function: FunctionId) -> Result<FnPointer> {
+    let mut base = try!(module_handle(module));
+    let mut symbol = match function {
+        FunctionId::Ordinal(ord) => Symbol::Ordinal(ord as u32),
+        FunctionId::Name(name) => Symbol::Name(try!(ascii_function_name(name)).as_bytes().to_vec())
+    };
+
+    for _ in 0..MAX_FORWARDS {
+        match try!(unsafe { find_export(base, &symbol) }) {
+            Target::Address(address) => return Ok(address),
+            Target::Forwarder(forwarder) => {
+                let (module, next) = try!(parse_forwarder(&forwarder));
+                base = try!(module_handle(OsStr::new(&module)));
+                symbol = next;
+            }
+        }
+    }
+
+    Err(Error::FunctionNotFound)
+}
+
+// A forwarder has the form `MODULE.Function` or `MODULE.#Ordinal`.
+fn parse_forwarder(forwarder: &[u8]) -> Result<(String, Symbol)> {
+    let dot = try!(forwarder.iter().rposition(|&c| c == b'.').ok_or(Error::FunctionNotFound));
+    let module = try!(String::from_utf8(forwarder[..dot].to_vec()).map_err(|_| Error::ModuleNotFound));
+    let function = &forwarder[dot + 1..];
+
+    let symbol = if function.first() == Some(&b'#') {
+        let ordinal = String::from_utf8_lossy(&function[1..]).parse::<u32>();
+        Symbol::Ordinal(try!(ordinal.map_err(|_| Error::FunctionNotFound)))
+    } else {
+        Symbol::Name(function.to_vec())
+    };
+
+    Ok((module + ".dll", symbol))
+}
+
+// Ordinals are indices into the function table, offset by the base ordinal of the module.
+pub unsafe fn check_ordinal(module: winapi::HMODULE, ordinal: u16) -> Result<()> {
+    let table = try!(ExportTable::new(module));
+    let base = table.directory.base;
+    let count = table.functions.len() as u32;
+
+    if (ordinal as u32) < base || ordinal as u32 - base >= count {
+        return Err(Error::OrdinalOutOfRange {
+            ordinal: ordinal,
+            base: base,
+            count: count
+        });
+    }
+    Ok(())
+}
+
+// Forwarded exports do not point to any code in the module and are skipped.
+pub unsafe fn exports(module: winapi::HMODULE) -> Result<Vec<Export>> {
+    let table = try!(ExportTable::new(module));
+
+    let mut names = vec![None; table.functions.len()];
+    for (&name, &index) in table.names.iter().zip(table.ordinals) {
+        if let Some(slot) = names.get_mut(index as usize) {
+            *slot = Some(String::from_utf8_lossy(table.string(name)).into_owned());
+        }
+    }
+
+    let mut exports = Vec::new();
+    for (index, (&rva, name)) in table.functions.iter().zip(names).enumerate() {
+        if let Some(Target::Address(address)) = table.target(rva) {
+            exports.push(Export {
+                name: name,
+                ordinal: (table.directory.base as usize + index) as u16,
+                address: address
+            });
+        }
+    }
+    Ok(exports)
+}
+
+unsafe fn find_export(module: winapi::HMODULE, symbol: &Symbol) -> Result<Target> {
+    let table = try!(ExportTable::new(module));
+
+    let index = match *symbol {
+        Symbol::Ordinal(ord) => ord.checked_sub(table.directory.base).map(|index| index as usize),
+        Symbol::Name(ref name) => {
+            table.names.iter()
+                 .position(|&rva| table.string(rva) == &name[..])
+                 .map(|position| table.ordinals[position] as usize)
+        }
+    };
+
+    index.and_then(|index| table.functions.get(index))
+         .and_then(|&rva| table.target(rva))
+         .ok_or(Error::FunctionNotFound)
+}
+
+struct ExportTable {
+    base: *const u8,
+    directory: &'static ExportDirectory,
+    directory_rva: u32,
+    directory_size: u32,
+    functions: &'static [u32],
+    names: &'static [u32],
+    ordinals: &'static [u16]
+}
+
+impl ExportTable {
+    // The slices in the returned table are only valid while the module stays loaded.
+    unsafe fn new(module: winapi::HMODULE) -> Result<ExportTable> {
+        let base = module as *const u8;
+
+        if *(base as *const u16) != DOS_SIGNATURE {
+            return Err(Error::FunctionNotFound);
+        }
+        let nt_headers = base.offset(*(base.offset(0x3c) as *const i32) as isize);
+        if *(nt_headers as *const u32) != NT_SIGNATURE {
+            return Err(Error::FunctionNotFound);
+        }
+
+        // The optional header follows the signature and the file header.
+        let optional_header = nt_headers.offset(24);
+        let data_directories = match *(optional_header as *const u16) {
+            PE32_MAGIC => optional_header.offset(96),
+            PE32_PLUS_MAGIC => optional_header.offset(112),
+            _ => return Err(Error::FunctionNotFound)
+        };
+
+        // The export directory is the first data directory.
+        let directory_rva = *(data_directories as *const u32);
+        let directory_size = *(data_directories.offset(4) as *const u32);
+        if directory_rva == 0 {
+            return Err(Error::FunctionNotFound);
+        }
+        let directory = &*(base.offset(directory_rva as isize) as *const ExportDirectory);
+
+        Ok(ExportTable {
+            base: base,
+            directory: directory,
+            directory_rva: directory_rva,
+            directory_size: directory_size,
+            functions: slice::from_raw_parts(base.offset(directory.address_of_functions as isize) as *const u32,
+                                             directory.number_of_functions as usize),
+            names: slice::from_raw_parts(base.offset(directory.address_of_names as isize) as *const u32,
+                                         directory.number_of_names as usize),
+            ordinals: slice::from_raw_parts(base.offset(directory.address_of_name_ordinals as isize) as *const u16,
+                                            directory.number_of_names as usize)
+        })
+    }
+
+    unsafe fn string(&self, rva: u32) -> &[u8] {
+        CStr::from_ptr(self.base.offset(rva as isize) as *const c_char).to_bytes()
+    }
+
+    unsafe fn target(&self, rva: u32) -> Option<Target> {
+        if rva == 0 {
+            None
+        } else if rva >= self.directory_rva && rva < self.directory_rva + self.directory_size {
+            // Forwarders are stored as strings inside of the export directory.
+            Some(Target::Forwarder(self.string(rva).to_vec()))
+        } else {
+            Some(Target::Address(FnPointer::from_raw(self.base.offset(rva as isize) as *mut _)))
+        }
+    }
+}
@@ -97,6 +97,9 @@ extern "system" {
 
     /// Creates a Hook for the specified API function, in disabled state.
     ///
+    /// Unlike `MH_CreateHookApi`, this function also returns the address of the
+    /// target function, which is needed to enable, disable or remove the hook.
+    ///
     /// # Arguments
     /// * `pszModule`  - A pointer to the loaded module name which contains the
     ///                  target function.
@@ -154,3 +157,55 @@ extern "system" {
     /// Applies all queued changes in one go.
     pub fn MH_ApplyQueued() -> MH_STATUS;
 }
+
+
+
+/// The instruction decoded by the Hacker Disassembler Engine that MinHook uses to relocate the
+/// instructions of the target function.
+#[cfg(target_arch = "x86")]
+#[repr(C, packed)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct hde_t {
+    /// The length of the instruction in bytes.
+    pub len: u8,
+    pub prefixes: [u8; 5],
+    /// The primary opcode byte.
+    pub opcode: u8,
+    pub opcode2: u8,
+    pub modrm_sib: [u8; 8],
+    pub imm: u32,
+    pub disp: u32,
+    /// The `F_*` flags of the instruction.
+    pub flags: u32
+}
+
+/// The instruction decoded by the Hacker Disassembler Engine that MinHook uses to relocate the
+/// instructions of the target function.
+#[cfg(target_arch = "x86_64")]
+#[repr(C, packed)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct hde_t {
+    /// The length of the instruction in bytes.
+    pub len: u8,
+    pub prefixes: [u8; 10],
+    /// The primary opcode byte.
+    pub opcode: u8,
+    pub opcode2: u8,
+    pub modrm_sib: [u8; 8],
+    pub imm: u64,
+    pub disp: u32,
+    /// The `F_*` flags of the instruction.
+    pub flags: u32
+}
+
+/// Set in `hde_t::flags` if the instruction could not be decoded.
+pub const F_ERROR: u32 = 0x0000_1000;
+
+extern "C" {
+    /// Decodes the instruction at the given address, returning its length.
+    #[cfg_attr(target_arch = "x86", link_name = "hde32_disasm")]
+    #[cfg_attr(target_arch = "x86_64", link_name = "hde64_disasm")]
+    pub fn hde_disasm(code: *const ::std::os::raw::c_void, hs: *mut hde_t) -> ::std::os::raw::c_uint;
+}
@@ -2,16 +2,28 @@
 //!
 //! The traits in this module are automatically implemented and should generally not be implemented
 //! by users of this library.
+//!
+//! # Structs passed by value
+//!
+//! Functions that take or return structs by value can be hooked like any other function, as long
+//! as the struct types are declared `#[repr(C)]` and match the layout the target function expects
+//! exactly. The detour function and the trampoline use the same signature as the target function,
+//! so the compiler passes these structs the same way on both sides, including the hidden pointer
+//! argument that most calling conventions use for returning larger structs. A struct type that is
+//! not `#[repr(C)]` or that has a different size than the actual struct can not be detected and
+//! silently corrupts arguments, return values or the stack.
 
-use std::{fmt, mem};
+use std::{fmt, mem, ptr};
 use std::os::raw::c_void;
 
-use super::Hook;
+use super::{DisabledHook, EnabledHook, Hook, StaticHook};
 
 
 
 /// An untyped function pointer.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Function pointers are compared, ordered and hashed by their address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FnPointer(*mut c_void);
 
 impl FnPointer {
@@ -23,10 +35,60 @@ impl FnPointer {
     /// executable memory.
     pub unsafe fn from_raw(ptr: *mut c_void) -> FnPointer { FnPointer(ptr) }
 
+    /// Creates a function pointer from an address.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it can not check if the argument points to valid
+    /// executable memory.
+    pub unsafe fn from_addr(addr: usize) -> FnPointer { FnPointer(addr as *mut c_void) }
+
+    /// Creates a function pointer from a typed function.
+    ///
+    /// This is equivalent to `f.to_ptr()`.
+    pub fn of<T: Function>(f: T) -> FnPointer { f.to_ptr() }
+
+    /// Converts this function pointer to a typed function.
+    ///
+    /// This is equivalent to `T::from_ptr(self)`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it can not check if this pointer points to a function
+    /// of type `T`. Calling the returned function is undefined behavior if it does not.
+    pub unsafe fn cast<T: Function>(self) -> T { T::from_ptr(self) }
+
     /// Returns function pointer as a raw pointer.
     pub fn to_raw(&self) -> *mut c_void { self.0 }
+
+    /// Returns the address of this function pointer.
+    pub fn addr(&self) -> usize { self.0 as usize }
+
+    /// Returns `true` if this is a null pointer.
+    pub fn is_null(&self) -> bool { self.0.is_null() }
+
+    /// Returns a function pointer that is offset by the given number of bytes from this one.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the resulting pointer might not point to valid executable
+    /// memory.
+    pub unsafe fn offset(&self, bytes: isize) -> FnPointer {
+        FnPointer::from_addr((self.addr() as isize).wrapping_add(bytes) as usize)
+    }
+}
+
+/// Returns a null function pointer, for example as a placeholder.
+///
+/// Hooks can not be created for or with a null function pointer.
+impl Default for FnPointer {
+    fn default() -> FnPointer { FnPointer(ptr::null_mut()) }
 }
 
+// A function pointer is just the address of some code, which is valid from any thread.
+unsafe impl Send for FnPointer {}
+unsafe impl Sync for FnPointer {}
+
 impl fmt::Pointer for FnPointer {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{:p}", self.0)
@@ -52,6 +114,9 @@ pub unsafe trait Function: Sized + Copy + Sync + 'static {
     /// The function's arity (number of arguments).
     const ARITY: usize;
 
+    /// The function's calling convention.
+    const CONVENTION: CallConv;
+
     /// Constructs a `Function` from an untyped function pointer.
     ///
     /// # Safety
@@ -65,6 +130,73 @@ pub unsafe trait Function: Sized + Copy + Sync + 'static {
 
     /// Returns this function as its unsafe variant.
     fn to_unsafe(&self) -> Self::Unsafe;
+
+    #[doc(hidden)]
+    fn __closure_detour<F>() -> Self
+    where F: Fn<Self::Args, Output = Self::Output> + Send + Sync + 'static;
+
+    /// Returns the function's arity (number of arguments).
+    fn arity(&self) -> usize {
+        Self::ARITY
+    }
+
+    /// Returns the function's calling convention.
+    fn convention(&self) -> CallConv {
+        Self::CONVENTION
+    }
+
+    /// Returns a description of this function's signature.
+    fn signature() -> SignatureInfo {
+        SignatureInfo {
+            arity: Self::ARITY,
+            convention: Self::CONVENTION
+        }
+    }
+}
+
+
+
+/// A calling convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallConv {
+    /// The Rust calling convention.
+    Rust,
+    /// The `extern "C"` calling convention.
+    C,
+    /// The `extern "cdecl"` calling convention.
+    Cdecl,
+    /// The `extern "stdcall"` calling convention.
+    Stdcall,
+    /// The `extern "fastcall"` calling convention.
+    Fastcall,
+    /// The `extern "win64"` calling convention.
+    Win64,
+    /// The `extern "system"` calling convention.
+    System,
+    /// The `extern "thiscall"` calling convention. Only available on x86.
+    Thiscall
+}
+
+
+
+/// A description of a function signature that is available at runtime.
+///
+/// This only describes the shape of a signature and not the argument and return types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignatureInfo {
+    /// The function's arity (number of arguments).
+    pub arity: usize,
+    /// The function's calling convention.
+    pub convention: CallConv
+}
+
+/// Returns `true` if a detour function with the given signature could be used to hook a target
+/// function with the given signature.
+///
+/// This only catches mismatches in arity and calling convention. Matching signatures are no
+/// guarantee that the argument and return types of both functions are compatible.
+pub fn signature_matches(target: &SignatureInfo, detour: &SignatureInfo) -> bool {
+    target.arity == detour.arity && target.convention == detour.convention
 }
 
 
@@ -75,14 +207,68 @@ pub unsafe trait UnsafeFunction: Function {}
 
 
 /// Marker trait indicating that the function `Self` can be hooked by the given function `D`.
+///
+/// A detour function has to have the same calling convention, argument types and return type as
+/// its target function. Hooking a function with a detour of a different calling convention
+/// corrupts the stack on x86, so this is rejected at compile time:
+///
+/// ```compile_fail
+/// extern "stdcall" fn target() {}
+/// extern "cdecl" fn detour() {}
+///
+/// unsafe { minhook::Hook::<extern "stdcall" fn()>::create(target, detour).unwrap(); }
+/// ```
 #[rustc_on_unimplemented = "The type `{D}` is not a suitable detour function type for a \
-                            target function of type `{Self}`."]
+                            target function of type `{Self}`. The calling conventions, \
+                            argument types and return types of both functions must match."]
 pub unsafe trait HookableWith<D: Function>: Function {}
 
 unsafe impl<T: Function> HookableWith<T> for T {}
 
 
 
+/// Marker trait indicating that the function `Self` can be hooked by the given diverging
+/// function `D`.
+///
+/// A diverging detour function never returns, so it is compatible with target functions of any
+/// return type, as long as the calling conventions and argument types match. This is a separate
+/// trait from `HookableWith` so that detour types can still be inferred for regular hooks.
+#[rustc_on_unimplemented = "The type `{D}` is not a suitable diverging detour function type for \
+                            a target function of type `{Self}`."]
+pub unsafe trait HookableWithDiverging<D: Function>: Function {}
+
+
+
+/// Marker trait indicating that the function `Self` can be hooked by the given function `D`,
+/// which is declared with a different but equivalent calling convention.
+///
+/// On x86, `extern "system"` is the same calling convention as `extern "stdcall"`, and on x64 it
+/// is the same as `extern "C"`. The function types are still distinct, so a detour declared with
+/// one of them is not accepted by `HookableWith` for a target declared with the other. This is a
+/// separate trait from `HookableWith` so that detour types can still be inferred for regular
+/// hooks.
+#[rustc_on_unimplemented = "The type `{D}` is not a detour function type with an equivalent \
+                            calling convention for a target function of type `{Self}`."]
+pub unsafe trait HookableWithEquivalentAbi<D: Function>: Function {}
+
+
+
+/// Trait mapping the argument types of a method, as a tuple, to the type of the function that
+/// implements the method for objects of type `C` with return type `Ret`.
+///
+/// The function takes a pointer to the object as its first argument. It uses the
+/// `extern "thiscall"` calling convention on x86 and the `extern "C"` calling convention on x64,
+/// matching the member functions of the Microsoft C++ compiler.
+pub unsafe trait MethodArgs<C, Ret>: Sized {
+    /// The function type implementing the method.
+    type Method: Function;
+
+    #[doc(hidden)]
+    unsafe fn __call(method: Self::Method, this: *mut C, args: Self) -> Ret;
+}
+
+
+
 #[cfg(not(feature = "increased_arity"))]
 impl_hookable! {
     __arg_0:  A, __arg_1:  B, __arg_2:  C, __arg_3:  D, __arg_4:  E, __arg_5:  F, __arg_6:  G,
@@ -94,5 +280,6 @@ impl_hookable! {
     __arg_0:  A, __arg_1:  B, __arg_2:  C, __arg_3:  D, __arg_4:  E, __arg_5:  F, __arg_6:  G,
     __arg_7:  H, __arg_8:  I, __arg_9:  J, __arg_10: K, __arg_11: L, __arg_12: M, __arg_13: N,
     __arg_14: O, __arg_15: P, __arg_16: Q, __arg_17: R, __arg_18: S, __arg_19: T, __arg_20: U,
-    __arg_21: V, __arg_22: W, __arg_23: X, __arg_24: Y, __arg_25: Z
+    __arg_21: V, __arg_22: W, __arg_23: X, __arg_24: Y, __arg_25: Z, __arg_26: A2, __arg_27: B2,
+    __arg_28: C2, __arg_29: D2, __arg_30: E2, __arg_31: F2
 }
@@ -3,36 +3,89 @@
 //! wrapper around the [MinHook][minhook] library.
 //!
 //! [minhook]: http://www.codeproject.com/KB/winsdk/LibMinHook.aspx
-#![feature(associated_consts,
+#![feature(abi_thiscall,
+           associated_consts,
            const_fn,
+           fn_traits,
+           never_type,
            on_unimplemented,
            unboxed_closures,
            drop_types_in_const)]
 #![cfg_attr(test, feature(static_recursion))]
+#![cfg_attr(test, feature(test))]
+#![cfg_attr(feature = "increased_arity", recursion_limit = "128")]
 #![warn(missing_docs)]
 #![allow(unknown_lints)]
 
+#[cfg(feature = "lazy_static")]
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "backtrace")]
+extern crate backtrace;
+#[cfg(feature = "futures")]
+extern crate futures;
+#[cfg(feature = "logging")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "libc")]
 extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(test)]
+extern crate test;
 extern crate kernel32;
 extern crate winapi;
 
-use std::{mem, ptr, result};
+use std::{fmt, mem, ptr, result};
+use std::cell::RefCell;
 use std::ffi::OsStr;
+use std::marker::PhantomData;
 use std::ops::Deref;
+use std::os::raw::c_void;
+use std::str::FromStr;
 use std::os::windows::ffi::OsStrExt;
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "lazy_static")]
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
-use function::{Function, FnPointer, HookableWith};
+use api::{MinHookApi, RealApi};
+use function::{Function, FnPointer, HookableWith, HookableWithDiverging, HookableWithEquivalentAbi};
 
-pub use error::Error;
+pub use chain::{ChainLink, HookChain, Next};
+pub use closure::ClosureHook;
+pub use error::{Error, HookError};
+pub use export::Export;
+pub use method::ThisHook;
+pub use module::Module;
+pub use spec::HookSpec;
 pub use sync::AtomicInitCell;
+pub use variadic::VariadicHook;
 
+mod api;
+mod chain;
+mod closure;
 mod error;
+mod export;
 mod ffi;
 #[macro_use] mod macros;
+mod method;
+mod module;
+mod patch;
+mod spec;
 mod sync;
+mod variadic;
+
+#[cfg(feature = "futures")]
+pub mod blocking;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 
 pub mod function;
 pub mod panic;
@@ -42,11 +95,36 @@ pub mod panic;
 /// Result type for most functions and methods in this module.
 pub type Result<T> = result::Result<T, Error>;
 
+/// Result type for the high-level hook operations in this module.
+pub type HookResult<T> = result::Result<T, HookError>;
+
+
+
+/// The number of hooks that were enabled and disabled by `HookQueue::apply_and_report()`.
+///
+/// Hooks that already were in the requested state are not counted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ApplyReport {
+    /// The number of disabled hooks that were enabled.
+    pub enabled: usize,
+    /// The number of enabled hooks that were disabled.
+    pub disabled: usize
+}
+
+#[cfg(feature = "lazy_static")]
+lazy_static! {
+    static ref QUEUE_LOCK: Mutex<()> = Mutex::new(());
+}
 
+#[cfg(not(feature = "lazy_static"))]
+static QUEUE_LOCK: sync::SpinLock<()> = sync::SpinLock::new();
 
 /// A queue of hook changes to be applied at once.
+///
+/// Every change has a priority, which is `0` unless it was queued with `enable_with_priority()`
+/// or `disable_with_priority()`.
 #[derive(Debug, Default)]
-pub struct HookQueue(Vec<(FnPointer, bool)>);
+pub struct HookQueue(Vec<(FnPointer, bool, i32)>);
 
 impl HookQueue {
     /// Create a new empty queue.
@@ -56,46 +134,166 @@ impl HookQueue {
 
     /// Queue the given hook to be enabled.
     pub fn enable<T: Function>(&mut self, hook: &Hook<T>) -> &mut HookQueue {
-        self.0.push((hook.target, true));
-        self
+        self.enable_with_priority(hook, 0)
     }
 
     /// Queue the given hook to be disabled.
     pub fn disable<T: Function>(&mut self, hook: &Hook<T>) -> &mut HookQueue {
-        self.0.push((hook.target, false));
+        self.disable_with_priority(hook, 0)
+    }
+
+    /// Queue the given hook to be enabled with the given priority.
+    ///
+    /// Changes with a higher priority are issued before changes with a lower priority when the
+    /// queue is applied. See `apply()`.
+    pub fn enable_with_priority<T: Function>(&mut self, hook: &Hook<T>, priority: i32) -> &mut HookQueue {
+        self.0.push((hook.target, true, priority));
+        self
+    }
+
+    /// Queue the given hook to be disabled with the given priority.
+    ///
+    /// See `enable_with_priority()`.
+    pub fn disable_with_priority<T: Function>(&mut self, hook: &Hook<T>, priority: i32) -> &mut HookQueue {
+        self.0.push((hook.target, false, priority));
+        self
+    }
+
+    /// Queue the hook for the given target function to be enabled.
+    ///
+    /// This is useful when only the target function of a hook is known, for example when it was
+    /// obtained using `all_hooks()`. If the hook has been destroyed by the time the queue is
+    /// applied, `apply()` returns a `NotCreated` error for the target function.
+    pub fn enable_ptr(&mut self, target: FnPointer) -> &mut HookQueue {
+        self.0.push((target, true, 0));
+        self
+    }
+
+    /// Queue the hook for the given target function to be disabled.
+    ///
+    /// See `enable_ptr()`.
+    pub fn disable_ptr(&mut self, target: FnPointer) -> &mut HookQueue {
+        self.0.push((target, false, 0));
+        self
+    }
+
+    /// Moves all the changes in the given queue to the end of this queue.
+    ///
+    /// This allows changes that were gathered separately to be applied at once.
+    pub fn append(&mut self, mut other: HookQueue) -> &mut HookQueue {
+        self.0.append(&mut other.0);
         self
     }
 
     /// Applies all the changes in this queue at once.
-    pub fn apply(&mut self) -> Result<()> {
-        lazy_static! {
-            static ref LOCK: Mutex<()> = Mutex::new(());
+    ///
+    /// Multiple changes queued for the same hook are collapsed into the last requested state
+    /// and priority before they are applied. The changes are then issued to MinHook in order of
+    /// decreasing priority, and changes with the same priority in the order in which they were
+    /// queued. This decides which hooks are enabled or disabled first when one change depends
+    /// on another, for example an IAT hook that calls into an inline hook.
+    ///
+    /// The changes are still applied by MinHook in a single transaction after all of them were
+    /// issued, so no other thread can run while only some of them have been applied.
+    ///
+    /// If a change was queued for a target function that has no hook, a `NotCreated` error for
+    /// that target function is returned and none of the changes are applied.
+    ///
+    /// While the changes are applied, MinHook suspends all other threads of the process, so that
+    /// none of them is executing the instructions that are being patched. The bundled MinHook
+    /// does not provide a way to skip this step. Threads that can not be suspended, for example
+    /// because of insufficient access rights, are skipped silently, so a failure to suspend a
+    /// thread is never reported as an error. Enabling or disabling a single hook suspends the
+    /// threads in the same way, so applying many changes at once keeps the number of times this
+    /// happens low.
+    pub fn apply(&mut self) -> HookResult<()> {
+        self.apply_and_report().map(|_| ())
+    }
+
+    /// Applies all the changes in this queue at once, returning how many hooks were enabled
+    /// and disabled.
+    ///
+    /// See `apply()`.
+    pub fn apply_and_report(&mut self) -> HookResult<ApplyReport> {
+        try!(initialize().map_err(|error| HookError::new(error, None, "apply queued hook changes")));
+
+        // Changes added through `Extend` or `enable_ptr()` do not necessarily refer to a hook. This
+        // only avoids suspending all threads in the common case, since another thread can still
+        // destroy a hook before its change is queued.
+        let hooks = all_hooks();
+        if let Some(&(target, _, _)) = self.0.iter().find(|&&(target, _, _)| !hooks.contains(&target)) {
+            return Err(HookError::new(Error::NotCreated, Some(target), "queue hook change"));
         }
 
-        try!(initialize());
-        let _lock = LOCK.lock().unwrap();
+        self.apply_with(&RealApi)
+    }
 
-        unsafe {
-            for &(target, enabled) in &self.0 {
-                // Any failure at this point is a bug.
-                if enabled {
-                    s2r(ffi::MH_QueueEnableHook(target.to_raw())).unwrap();
-                } else {
-                    s2r(ffi::MH_QueueDisableHook(target.to_raw())).unwrap();
+    fn apply_with(&mut self, api: &MinHookApi) -> HookResult<ApplyReport> {
+        // The lock does not protect any data, so a panic while holding it can not leave anything
+        // in an inconsistent state.
+        let _lock = QUEUE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let changes = self.deduplicated();
+        for (index, &(target, enabled)) in changes.iter().enumerate() {
+            let result = if enabled { api.queue_enable(target) } else { api.queue_disable(target) };
+            log_result!(&result, "queue {} hook for {:p}", if enabled { "enable" } else { "disable" }, target);
+            if let Err(error) = result {
+                // MinHook keeps the changes that were already queued until the next time queued
+                // changes are applied, so queue the current state of those hooks instead.
+                for &(target, _) in &changes[..index] {
+                    let _ = if is_enabled(target) { api.queue_enable(target) } else { api.queue_disable(target) };
                 }
+                return Err(HookError::new(error, Some(target), "queue hook change"));
+            }
+        }
+
+        let result = api.apply_queued();
+        log_result!(&result, "apply queued hook changes");
+        try!(result.map_err(|error| HookError::new(error, None, "apply queued hook changes")));
+
+        let mut report = ApplyReport::default();
+        for &(target, enabled) in &changes {
+            match (set_enabled(target, enabled), enabled) {
+                (true, true) => report.enabled += 1,
+                (true, false) => report.disabled += 1,
+                (false, _) => ()
             }
-            s2r(ffi::MH_ApplyQueued())
         }
+        Ok(report)
+    }
+
+    fn deduplicated(&self) -> Vec<(FnPointer, bool)> {
+        let mut changes: Vec<(FnPointer, bool, i32)> = Vec::with_capacity(self.0.len());
+        for &(target, enabled, priority) in &self.0 {
+            if let Some(change) = changes.iter_mut().find(|change| change.0 == target) {
+                change.1 = enabled;
+                change.2 = priority;
+                continue;
+            }
+            changes.push((target, enabled, priority));
+        }
+
+        // The sort is stable, so changes with the same priority keep their order.
+        changes.sort_by(|a, b| b.2.cmp(&a.2));
+        changes.into_iter().map(|(target, enabled, _)| (target, enabled)).collect()
+    }
+}
+
+/// Queues the hooks for the given target functions to be enabled (`true`) or disabled (`false`).
+impl Extend<(FnPointer, bool)> for HookQueue {
+    fn extend<I: IntoIterator<Item = (FnPointer, bool)>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(|(target, enabled)| (target, enabled, 0)));
     }
 }
 
 
 
 /// A hook that is destroyed when it goes out of scope.
-#[derive(Debug)]
 pub struct Hook<T: Function> {
     target: FnPointer,
-    trampoline: T
+    trampoline: T,
+    detour: FnPointer,
+    original: [u8; patch::SAVED_BYTES]
 }
 
 impl<T: Function> Hook<T> {
@@ -106,6 +304,24 @@ impl<T: Function> Hook<T> {
     /// called. An invocation of the target function might for example get inlined in which case
     /// it is impossible to hook at runtime.
     ///
+    /// If the target function does not point to executable memory, a `NotExecutableDetail`
+    /// error containing the protection flags of the memory region is returned.
+    ///
+    /// A target function of an `unsafe` function type, such as most functions of the Windows API,
+    /// can be hooked with a detour function of either the safe or the `unsafe` version of that
+    /// type. Calling the original function through `call_real()` is `unsafe` for such hooks.
+    ///
+    /// Functions can be passed by name without casting them to function pointers, as long as the
+    /// function type `T` is known, either by naming it or from the type of the result:
+    ///
+    /// ```ignore
+    /// let hook = try!(Hook::<fn(i32) -> i32>::create(target, detour));
+    /// let hook: Hook<fn(i32) -> i32> = try!(Hook::create(target, detour));
+    /// ```
+    ///
+    /// Each function has its own unique type that can not be named or converted to a function
+    /// pointer generically, so `T` can not be inferred from the function itself.
+    ///
     /// # Safety
     ///
     /// The given target function type must uniquely match the actual target function. This
@@ -113,19 +329,289 @@ impl<T: Function> Hook<T> {
     /// can not be two function pointers with different signatures pointing to the same
     /// code location. This last situation can for example happen when the Rust compiler
     /// or LLVM decide to merge multiple functions with the same code into one.
-    pub unsafe fn create<D>(target: T, detour: D) -> Result<Hook<T>>
+    pub unsafe fn create<D>(target: T, detour: D) -> HookResult<Hook<T>>
+    where T: HookableWith<D>, D: Function {
+        Hook::create_at(target.to_ptr(), detour)
+    }
+
+    /// Create a new hook given a target function and a compatible detour function that never
+    /// returns.
+    ///
+    /// The hook is disabled by default.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`.
+    pub unsafe fn create_diverging<D>(target: T, detour: D) -> HookResult<Hook<T>>
+    where T: HookableWithDiverging<D>, D: Function {
+        Hook::create_at_unchecked(target.to_ptr(), detour.to_ptr())
+    }
+
+    /// Create a new hook given a target function and a detour closure.
+    ///
+    /// Unlike the detour closure of a `StaticHook`, the closure does not have to be declared
+    /// using the `static_hooks!` macro. It may capture any state, but it is called from every
+    /// thread that calls the target function, so it has to be `Send` and `Sync`. The closure is
+    /// dropped when the hook is destroyed. A panic in the closure is handled by the detour panic
+    /// handler, like a panic in the detour closure of a static hook.
+    ///
+    /// Every hook gets its own small thunk of executable memory that jumps to the detour closure,
+    /// so closures of the same type can be used for any number of hooks at the same time.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`.
+    pub unsafe fn create_closure<F>(target: T, closure: F) -> HookResult<ClosureHook<T>>
+    where F: Fn<T::Args, Output = T::Output> + Send + Sync + 'static {
+        closure::create(target, closure)
+    }
+
+    /// Create a new hook given a target function and the address of a detour function.
+    ///
+    /// This is useful when the detour function is only known by its address, for example when
+    /// it is generated at runtime. The hook is disabled by default.
+    ///
+    /// # Safety
+    ///
+    /// The detour address must point to a function with a signature that matches the target
+    /// function type `T`, including its calling convention. This is not checked.
+    ///
+    /// See `create()` for more safety requirements.
+    pub unsafe fn create_with_ptr_detour(target: T, detour: FnPointer) -> HookResult<Hook<T>> {
+        Hook::create_at_unchecked(target.to_ptr(), detour)
+    }
+
+    /// Create a new hook given a target function and a compatible detour function, letting the
+    /// hook jump to the detour function through a relay at the given address.
+    ///
+    /// If `relay_hint` is `None` or null, this behaves exactly like `create()`. Otherwise a jump
+    /// to the detour function is written to the given address, and the target function is
+    /// patched to jump there instead of to the detour function directly. The memory at that
+    /// address has to be executable and at least 14 bytes (x64) or 5 bytes (x86) large. It is
+    /// made writable for the duration of the write and its protection is restored afterwards,
+    /// so read-only code such as a code cave of the target module can be used as well. If the
+    /// hook can not be created, the original contents of the memory are restored. It can for
+    /// example be allocated close to the target function:
+    ///
+    /// ```ignore
+    /// let relay = VirtualAlloc(near_target, 4096, MEM_COMMIT | MEM_RESERVE, PAGE_EXECUTE_READWRITE);
+    /// let hook = try!(Hook::<fn(i32) -> i32>::create_with_relay(target, detour, Some(relay)));
+    /// ```
+    ///
+    /// A relay does not prevent `MemoryAlloc` errors. On x64, MinHook allocates the trampoline
+    /// function within 1 GB of the target function no matter where the relay is, and that
+    /// allocation fails in the same way as without a relay when the address space near the target
+    /// function is exhausted.
+    ///
+    /// The hook otherwise behaves like a hook created with `create()` using the same detour
+    /// function, for example when it is looked up with `create_or_get()`.
+    ///
+    /// # Safety
+    ///
+    /// The relay memory must remain valid and unmodified for the entire duration of the hook.
+    ///
+    /// See `create()` for more safety requirements.
+    pub unsafe fn create_with_relay<D>(target: T, detour: D, relay_hint: Option<*mut c_void>) -> HookResult<Hook<T>>
+    where T: HookableWith<D>, D: Function {
+        let (target, detour) = (target.to_ptr(), detour.to_ptr());
+        let relay = match relay_hint {
+            Some(relay) if !relay.is_null() => FnPointer::from_raw(relay),
+            _ => return Hook::create_at_unchecked(target, detour)
+        };
+        let context = |error| HookError::new(error, Some(target), "create hook");
+
+        try!(check_executable(relay).map_err(&context));
+        let original = patch::read_relay(relay.to_raw() as *const u8);
+        let result = patch_relay(relay, &patch::relay_code(relay.to_raw() as *const u8, detour))
+            .and_then(|_| Hook::create_relayed_with(&RealApi, target, detour, relay));
+        result.map_err(|error| {
+            let _ = patch_relay(relay, &original);
+            context(error)
+        })
+    }
+
+    /// Create a new hook given a target function and a detour function that is declared with an
+    /// equivalent calling convention, such as `extern "stdcall"` for an `extern "system"` target
+    /// function on x86.
+    ///
+    /// The hook is disabled by default. See `HookableWithEquivalentAbi`.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`.
+    pub unsafe fn create_equivalent_abi<D>(target: T, detour: D) -> HookResult<Hook<T>>
+    where T: HookableWithEquivalentAbi<D>, D: Function {
+        Hook::create_at_unchecked(target.to_ptr(), detour.to_ptr())
+    }
+
+    /// Create a new hook given a target function and a compatible detour function, retrying
+    /// if the target function can not be hooked at the moment.
+    ///
+    /// Creating the hook is attempted up to `attempts` times, sleeping for `delay` in between,
+    /// as long as it fails with `UnsupportedFunction` or `MemoryProtect`. These errors can be
+    /// transient if another thread, for example a loader or a JIT compiler, is patching the
+    /// target function at the same time. The hook is disabled by default.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`.
+    pub unsafe fn create_with_retry<D>(target: T, detour: D, attempts: u32, delay: Duration) -> HookResult<Hook<T>>
+    where T: HookableWith<D>, D: Function {
+        Hook::create_with_retry_with(&RealApi, target.to_ptr(), detour.to_ptr(), attempts, delay)
+    }
+
+    unsafe fn create_with_retry_with(api: &MinHookApi, target: FnPointer, detour: FnPointer, attempts: u32, delay: Duration) -> HookResult<Hook<T>> {
+        retry(attempts, delay, || Hook::create_at_with(api, target, detour))
+            .map_err(|error| HookError::new(error, Some(target), "create hook"))
+    }
+
+    /// Create a new hook given the address of a target function and a compatible detour
+    /// function.
+    ///
+    /// The hook is disabled by default.
+    ///
+    /// The target address does not have to be the start of a function. A function can for example
+    /// be hooked past its prologue by offsetting its address:
+    ///
+    /// ```ignore
+    /// let function = try!(minhook::resolve("module.dll", FunctionId::name("Function")));
+    /// let hook = try!(Hook::<extern "system" fn() -> u32>::create_at(function.offset(3), detour));
+    /// ```
+    ///
+    /// MinHook relocates the instructions at the target address into the trampoline function. If
+    /// these instructions can not be split off safely, for example because the code returns
+    /// before there is room for a jump, an `UnsupportedFunction` error is returned. `can_hook()`
+    /// can be used to find out why beforehand.
+    ///
+    /// # Safety
+    ///
+    /// The target address must point to a function of type `T`. When hooking inside of a
+    /// function, this means that the detour function and the trampoline function have to behave
+    /// like the rest of the function from the target address on. In particular the stack has to
+    /// be laid out exactly as on entry to a function of type `T`, and the target address must be
+    /// at an instruction boundary.
+    ///
+    /// See `create()` for more safety requirements.
+    pub unsafe fn create_at<D>(target: FnPointer, detour: D) -> HookResult<Hook<T>>
+    where T: HookableWith<D>, D: Function {
+        Hook::create_at_unchecked(target, detour.to_ptr())
+    }
+
+    /// Create a new disabled hook given a target function and a compatible detour function.
+    ///
+    /// Unlike `create()`, the returned hook tracks whether it is enabled in its type. See
+    /// `DisabledHook` for more information.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`.
+    pub unsafe fn new_disabled<D>(target: T, detour: D) -> HookResult<DisabledHook<T>>
+    where T: HookableWith<D>, D: Function {
+        Hook::create(target, detour).map(DisabledHook)
+    }
+
+    /// Create a new hook given a target function and a compatible detour function, or return
+    /// another handle to the existing hook if this target function is already hooked with this
+    /// detour function.
+    ///
+    /// This makes code that may run more than once, such as the initialization of a library,
+    /// idempotent. All handles refer to the same hook: enabling or disabling it through one
+    /// handle affects all of them, and it is only removed when the last handle is dropped or
+    /// removed. If the target function is hooked with a different detour function, an
+    /// `AlreadyCreated` error is returned.
+    ///
+    /// # Safety
+    ///
+    /// See `create()`. The existing hook must have been created with the same target function
+    /// type.
+    pub unsafe fn create_or_get<D>(target: T, detour: D) -> HookResult<Hook<T>>
     where T: HookableWith<D>, D: Function {
+        let (target, detour) = (target.to_ptr(), detour.to_ptr());
+        if let Some(hook) = acquire(target, detour) {
+            return Ok(hook);
+        }
+
+        // Another thread may have created the hook in the meantime.
+        Hook::create_at_unchecked(target, detour).or_else(|error| match error.source {
+            Error::AlreadyCreated => acquire(target, detour).ok_or(error),
+            _ => Err(error)
+        })
+    }
+
+    unsafe fn create_at_unchecked(target: FnPointer, detour: FnPointer) -> HookResult<Hook<T>> {
+        Hook::create_at_raw(target, detour).map_err(|error| HookError::new(error, Some(target), "create hook"))
+    }
+
+    unsafe fn create_at_raw(target: FnPointer, detour: FnPointer) -> Result<Hook<T>> {
+        Hook::create_at_with(&RealApi, target, detour)
+    }
+
+    unsafe fn create_at_with(api: &MinHookApi, target: FnPointer, detour: FnPointer) -> Result<Hook<T>> {
+        Hook::create_relayed_with(api, target, detour, detour)
+    }
+
+    // Creates a hook that jumps to the given relay, which has to jump to the detour function in
+    // turn. Without a relay, the relay is the detour function itself.
+    unsafe fn create_relayed_with(api: &MinHookApi, target: FnPointer, detour: FnPointer, relay: FnPointer) -> Result<Hook<T>> {
+        let _creating = Creating::begin();
         try!(initialize());
+        if target.is_null() || detour.is_null() {
+            return Err(Error::NotExecutable);
+        }
+        if target == detour {
+            return Err(Error::TargetIsDetour);
+        }
+        try!(check_executable(target));
+        let original = patch::read(target);
 
-        let target = target.to_ptr();
-        let detour = detour.to_ptr();
-        let mut trampoline = mem::uninitialized();
-        try!(s2r(ffi::MH_CreateHook(target.to_raw(), detour.to_raw(), &mut trampoline)));
+        let result = measure!(Create, |_: &Result<_>| Some(target), api.create(target, relay));
+        log_result!(&result, "create hook for {:p} with detour {:p}", target, detour);
+        let trampoline = try!(result);
 
-        Ok(Hook {
+        Ok(register(Hook {
             target: target,
-            trampoline: T::from_ptr(FnPointer::from_raw(trampoline)),
-        })
+            trampoline: T::from_ptr(trampoline),
+            detour: detour,
+            original: original
+        }))
+    }
+
+    /// Create a new hook given the name of the module, the relative virtual address (RVA) of the
+    /// target function inside of that module and a compatible detour function.
+    ///
+    /// The module has to be loaded before this function is called. This function does not
+    /// attempt to load the module first. The hook is disabled by default.
+    ///
+    /// # Safety
+    ///
+    /// The target module must remain loaded in memory for the entire duration of the hook.
+    ///
+    /// See `create_at()` for more safety requirements.
+    pub unsafe fn create_rva<M, D>(target_module: M, target_rva: usize, detour: D) -> HookResult<Hook<T>>
+    where M: AsRef<OsStr>, T: HookableWith<D>, D: Function {
+        let base = try!(module_handle(target_module.as_ref())
+                            .map_err(|error| HookError::new(error, None, "find target module")));
+        Hook::create_at(FnPointer::from_raw((base as usize + target_rva) as *mut _), detour)
+    }
+
+    /// Create a new hook given the name of the module, the ordinal value of the target function
+    /// and a compatible detour function.
+    ///
+    /// The address of the target function is looked up using `resolve()` first, after which the
+    /// hook is created using `create_at()`. The module has to be loaded before this function is
+    /// called. This function does not attempt to load the module first. The hook is disabled by
+    /// default.
+    ///
+    /// # Safety
+    ///
+    /// The target module must remain loaded in memory for the entire duration of the hook.
+    ///
+    /// See `create_at()` for more safety requirements.
+    pub unsafe fn create_api_ordinal_typed<M, D>(target_module: M, target_ordinal: u16, detour: D) -> HookResult<Hook<T>>
+    where M: AsRef<OsStr>, T: HookableWith<D>, D: Function {
+        let target = try!(resolve(target_module, FunctionId::ordinal(target_ordinal))
+                              .map_err(|error| HookError::new(error, None, "resolve target function")));
+        Hook::create_at(target, detour)
     }
 
     /// Create a new hook given the name of the module, the name of the function symbol and a
@@ -139,53 +625,87 @@ impl<T: Function> Hook<T> {
     /// The target module must remain loaded in memory for the entire duration of the hook.
     ///
     /// See `create()` for more safety requirements.
-    pub unsafe fn create_api<M, D>(target_module: M, target_function: FunctionId, detour: D) -> Result<Hook<T>>
+    pub unsafe fn create_api<M, D>(target_module: M, target_function: FunctionId, detour: D) -> HookResult<Hook<T>>
     where M: AsRef<OsStr>, T: HookableWith<D>, D: Function {
-        fn str_to_wstring(string: &OsStr) -> Option<Vec<winapi::WCHAR>> {
-            let mut wide = string.encode_wide().collect::<Vec<_>>();
-            if wide.contains(&0) {
-                return None;
-            }
-            wide.push(0);
-            Some(wide)
-        }
-
-        try!(initialize());
-
-        let module_name = try!(str_to_wstring(target_module.as_ref()).ok_or(Error::InvalidModuleName));
-
-        let (function_name, _data) = match target_function {
-            FunctionId::Ordinal(ord) => (ord as winapi::LPCSTR, Vec::new()),
-            FunctionId::Name(name) => {
-                let symbol_name_wide = try!(str_to_wstring(name).ok_or(Error::InvalidFunctionName));
+        Hook::create_api_with(&RealApi, target_module, target_function, detour.to_ptr())
+    }
 
-                let size = kernel32::WideCharToMultiByte(winapi::CP_ACP, 0, symbol_name_wide.as_ptr(), -1, ptr::null_mut(), 0, ptr::null(), ptr::null_mut());
-                if size == 0 {
-                    return Err(Error::InvalidFunctionName);
-                }
+    unsafe fn create_api_with<M>(api: &MinHookApi, target_module: M, target_function: FunctionId, detour: FnPointer) -> HookResult<Hook<T>>
+    where M: AsRef<OsStr> {
+        // The target function is not known until the hook is created.
+        let context = |error| HookError::new(error, None, "create hook for exported function");
+        let _creating = Creating::begin();
+        try!(initialize().map_err(&context));
 
-                let mut buffer = Vec::with_capacity(size as usize);
-                buffer.set_len(size as usize);
+        let module_name = try!(str_to_wstring(target_module.as_ref()).map_err(&context));
+        let (function_name, _data) = try!(function_id_to_raw(&target_function).map_err(&context));
 
-                let size = kernel32::WideCharToMultiByte(winapi::CP_ACP, 0, symbol_name_wide.as_ptr(), -1, buffer.as_mut_ptr(), size, ptr::null(), ptr::null_mut());
-                if size == 0 {
-                    return Err(Error::InvalidFunctionName);
-                }
+        let result = measure!(Create,
+                              |result: &Result<(FnPointer, FnPointer)>| result.as_ref().ok().map(|&(_, target)| target),
+                              api.create_api(module_name.as_ptr(), function_name, detour));
+        log_result!(&result, "create hook for {:?} in {:?} with detour {:p}", target_function, target_module.as_ref(), detour);
+        let (trampoline, target) = try!(result.map_err(&context));
 
-                (buffer.as_ptr(), buffer)
-            }
-        };
+        // The target is only known after the hook has been created.
+        if target == detour {
+            let _ = api.remove(target);
+            return Err(HookError::new(Error::TargetIsDetour, Some(target), "create hook for exported function"));
+        }
 
-        let detour = detour.to_ptr();
-        let mut trampoline = mem::uninitialized();
-        let mut target = mem::uninitialized();
+        Ok(register(Hook {
+            target: target,
+            trampoline: T::from_ptr(trampoline),
+            detour: detour,
+            // Creating a hook does not modify the target function yet.
+            original: patch::read(target)
+        }))
+    }
 
-        try!(s2r(ffi::MH_CreateHookApiEx(module_name.as_ptr(), function_name, detour.to_raw(), &mut trampoline, &mut target)));
+    /// Create a new hook given the name of the module, the name of the function symbol and a
+    /// compatible detour function, loading the module first if necessary.
+    ///
+    /// The returned `Module` keeps the target module loaded. The hook is disabled by default.
+    ///
+    /// An ordinal value is checked against the export table of the module before the hook is
+    /// created, so that an ordinal the module does not export results in an `OrdinalOutOfRange`
+    /// error.
+    ///
+    /// # Safety
+    ///
+    /// Dropping the returned `Module` may unload the target module, which invalidates the hook.
+    /// The hook must therefore be dropped before the module.
+    ///
+    /// See `create()` for more safety requirements.
+    pub unsafe fn create_api_loading<M, D>(target_module: M, target_function: FunctionId, detour: D) -> HookResult<(Hook<T>, Module)>
+    where M: AsRef<OsStr>, T: HookableWith<D>, D: Function {
+        let module = try!(Module::load(target_module.as_ref())
+                              .map_err(|error| HookError::new(error, None, "load target module")));
+        if let FunctionId::Ordinal(ordinal) = target_function {
+            try!(export::check_ordinal(module.handle(), ordinal)
+                        .map_err(|error| HookError::new(error, None, "resolve target function")));
+        }
+        let hook = try!(Hook::create_api(target_module, target_function, detour));
+        Ok((hook, module))
+    }
 
-        Ok(Hook {
-            target: FnPointer::from_raw(target),
-            trampoline: T::from_ptr(FnPointer::from_raw(trampoline)),
-        })
+    /// Create a new hook given the name of the module, the name of the function symbol and a
+    /// compatible detour function, following any export forwarders to the module that actually
+    /// implements the function.
+    ///
+    /// All modules involved have to be loaded before this function is called. This function does
+    /// not attempt to load them first. The hook is disabled by default. See `resolve_forwarded()`.
+    ///
+    /// # Safety
+    ///
+    /// The module that implements the target function must remain loaded in memory for the
+    /// entire duration of the hook.
+    ///
+    /// See `create()` for more safety requirements.
+    pub unsafe fn create_api_resolved<M, D>(target_module: M, target_function: FunctionId, detour: D) -> HookResult<Hook<T>>
+    where M: AsRef<OsStr>, T: HookableWith<D>, D: Function {
+        let target = try!(resolve_forwarded(target_module, target_function)
+                              .map_err(|error| HookError::new(error, None, "resolve target function")));
+        Hook::create_at(target, detour)
     }
 
     /// Returns a pointer to the trampoline function.
@@ -196,58 +716,515 @@ impl<T: Function> Hook<T> {
         self.trampoline.to_unsafe()
     }
 
+    /// Returns `true` if the given function is the target function of this hook.
+    pub fn targets<F: Function>(&self, function: &F) -> bool {
+        self.target == function.to_ptr()
+    }
+
+    /// Returns the first bytes of the target function as they were when this hook was created.
+    pub fn original_bytes(&self) -> Vec<u8> {
+        self.original.to_vec()
+    }
+
+    /// Returns `true` if the start of the target function is in the state MinHook left it in.
+    ///
+    /// While the hook is disabled, the target function has to start with its original bytes.
+    /// While it is enabled, it has to start with the jump to the detour function written by
+    /// MinHook. Any other state means that the code was modified by someone else, for example by
+    /// another hooking library.
+    pub fn verify_intact(&self) -> bool {
+        unsafe { patch::read(self.target) == self.original || patch::jumps_to(self.target, self.detour) }
+    }
+
     /// Enables this hook.
     ///
     /// Consider using a `HookQueue` if you want to enable/disable a large amount of hooks at once.
-    pub fn enable(&self) -> Result<()> {
-        unsafe { s2r(ffi::MH_EnableHook(self.target.to_raw())) }
+    pub fn enable(&self) -> HookResult<()> {
+        self.enable_with(&RealApi)
+    }
+
+    fn enable_with(&self, api: &MinHookApi) -> HookResult<()> {
+        let result = measure!(Enable, |_: &Result<_>| Some(self.target), api.enable(self.target));
+        log_result!(&result, "enable hook for {:p}", self.target);
+        try!(result.map_err(|error| HookError::new(error, Some(self.target), "enable hook")));
+        set_enabled(self.target, true);
+        Ok(())
     }
 
     /// Disables this hook.
     ///
     /// Consider using a `HookQueue` if you want to enable/disable a large amount of hooks at once.
-    pub fn disable(&self) -> Result<()> {
-        unsafe { s2r(ffi::MH_DisableHook(self.target.to_raw())) }
+    pub fn disable(&self) -> HookResult<()> {
+        self.disable_with(&RealApi)
     }
-}
 
-impl<T: Function> Drop for Hook<T> {
-    fn drop(&mut self) {
-        let _ = unsafe { s2r(ffi::MH_RemoveHook(self.target.to_raw())) };
+    fn disable_with(&self, api: &MinHookApi) -> HookResult<()> {
+        let result = measure!(Disable, |_: &Result<_>| Some(self.target), api.disable(self.target));
+        log_result!(&result, "disable hook for {:p}", self.target);
+        try!(result.map_err(|error| HookError::new(error, Some(self.target), "disable hook")));
+        set_enabled(self.target, false);
+        Ok(())
     }
-}
 
-// Synchronization is done in the MinHook library.
-unsafe impl<T: Function> Sync for Hook<T> {}
-unsafe impl<T: Function> Send for Hook<T> {}
+    /// Temporarily disables this hook until the returned guard goes out of scope.
+    ///
+    /// While the guard is alive, calls to the target function reach the original function. This
+    /// can for example be used to call the target function from within a detour function without
+    /// recursing. The hook is only re-enabled if it was enabled when this method was called.
+    pub fn bypass_scoped(&self) -> HookResult<BypassGuard<T>> {
+        let enabled = try!(self.disable_if_enabled());
 
+        Ok(BypassGuard {
+            hook: self,
+            enabled: enabled
+        })
+    }
 
+    /// Enables all the given hooks at once and disables them again at once when the returned
+    /// guard goes out of scope.
+    ///
+    /// Both changes are applied using a `HookQueue`. Any error that occurs while disabling the
+    /// hooks is ignored.
+    pub fn apply_queue_scoped<'a>(hooks: &[&'a Hook<T>]) -> HookResult<QueueGuard<'a>> {
+        let mut queue = HookQueue::new();
+        for hook in hooks {
+            queue.enable(hook);
+        }
+        try!(queue.apply());
 
-/// A function identifier used for dynamically looking up a function.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum FunctionId<'a> {
-    /// The function's ordinal value.
-    Ordinal(u16),
-    /// The function's name.
-    Name(&'a OsStr)
-}
+        Ok(QueueGuard {
+            targets: hooks.iter().map(|hook| hook.target).collect(),
+            marker: PhantomData
+        })
+    }
 
-impl<'a> FunctionId<'a> {
-    /// Create a function identifier given it's ordinal value.
-    pub fn ordinal(value: u16) -> FunctionId<'static> {
-        FunctionId::Ordinal(value)
+    // Returns whether the hook was enabled.
+    fn disable_if_enabled(&self) -> HookResult<bool> {
+        // MinHook does not report whether a hook is enabled, so find out by disabling it.
+        match self.disable() {
+            Ok(()) => Ok(true),
+            Err(HookError { source: Error::Disabled, .. }) => Ok(false),
+            Err(error) => Err(error)
+        }
     }
 
-    /// Create a function identifier given it's string name.
-    pub fn name<N: ?Sized + AsRef<OsStr> + 'a>(name: &'a N) -> FunctionId<'a> {
-        FunctionId::Name(name.as_ref())
+    /// Removes this hook, returning a pointer to the target function.
+    ///
+    /// Unlike dropping the hook, this reports any error that occurs while removing the hook. The
+    /// removal is not attempted again in either case. If other handles to this hook that were
+    /// returned by `create_or_get()` are still alive, only this handle is released.
+    pub fn remove(self) -> HookResult<FnPointer> {
+        self.remove_with(&RealApi)
+    }
+
+    fn remove_with(self, api: &MinHookApi) -> HookResult<FnPointer> {
+        let target = self.target;
+        let result = self.release_with(api);
+        mem::forget(self);
+        result.map(|_| target).map_err(|error| HookError::new(error, Some(target), "remove hook"))
+    }
+
+    // Releases this handle, removing the hook if it was the last one.
+    fn release_with(&self, api: &MinHookApi) -> Result<()> {
+        if !unregister(self.target) {
+            return Ok(());
+        }
+        let result = api.remove(self.target);
+        log_result!(&result, "remove hook for {:p}", self.target);
+        result
     }
 }
 
+impl<T> Hook<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    /// Calls the original function with the given arguments, unless a call to the original
+    /// function through this method is already in progress for this hook on the current thread.
+    ///
+    /// The trampoline of some target functions re-enters the hooked function, which calls the
+    /// detour function again. A detour function that calls the original function using
+    /// `call_real()` would then recurse infinitely. When this method is used instead, the nested
+    /// call returns `None` without calling the original function, so the detour function can
+    /// handle it in some other way.
+    pub fn call_original_safely(&self, args: T::Args) -> Option<T::Output> {
+        struct Guard(FnPointer);
 
-/// A hook with a static lifetime.
-///
-/// This hook can only be constructed using the `static_hooks!` macro. It has one of the
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                ACTIVE_CALLS.with(|calls| {
+                    let mut calls = calls.borrow_mut();
+                    if let Some(index) = calls.iter().rposition(|&target| target == self.0) {
+                        calls.remove(index);
+                    }
+                });
+            }
+        }
+
+        let entered = ACTIVE_CALLS.with(|calls| {
+            let mut calls = calls.borrow_mut();
+            if calls.contains(&self.target) {
+                return false;
+            }
+            calls.push(self.target);
+            true
+        });
+        if !entered {
+            return None;
+        }
+
+        // Leaves the original function again, even if it panics.
+        let _guard = Guard(self.target);
+        Some(Fn::call(&self.trampoline, args))
+    }
+
+    /// Returns the trampoline function as a closure that borrows this hook.
+    ///
+    /// Unlike the function pointer returned by `trampoline()`, the closure can not outlive the
+    /// hook, so it can be called safely.
+    pub fn trampoline_fn(&self) -> Trampoline<T> {
+        Trampoline {
+            trampoline: self.trampoline,
+            marker: PhantomData
+        }
+    }
+
+    /// Returns the trampoline function as a shared closure that keeps this hook alive.
+    ///
+    /// The closure holds its own handle to the hook, in the same way as `create_or_get()`, so
+    /// the hook is only removed once this hook and the last clone of the returned `Arc` are
+    /// dropped. The closure can therefore be called safely from any thread for as long as it
+    /// exists. Dropping this hook does not disable the hook either.
+    ///
+    /// Reinitializing or destroying a static hook removes the old hook regardless of any
+    /// handles. If this hook was removed in that way, a `NotCreated` error is returned.
+    pub fn original(&self) -> HookResult<Arc<OriginalFn<T>>> {
+        let hook = unsafe { acquire(self.target, self.detour) };
+        hook.map(|hook| Arc::new(OriginalFn(hook)))
+            .ok_or_else(|| HookError::new(Error::NotCreated, Some(self.target), "share original function"))
+    }
+}
+
+/// The trampoline function of a hook, borrowing the hook.
+///
+/// This value can be called like the target function, with the arguments passed as a tuple when
+/// using `call()`. See `Hook::trampoline_fn()`.
+pub struct Trampoline<'a, T: Function + 'a> {
+    trampoline: T,
+    marker: PhantomData<&'a Hook<T>>
+}
+
+impl<'a, T> Fn<<T as Function>::Args> for Trampoline<'a, T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call(&self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&self.trampoline, args)
+    }
+}
+
+impl<'a, T> FnMut<<T as Function>::Args> for Trampoline<'a, T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call_mut(&mut self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&*self, args)
+    }
+}
+
+impl<'a, T> FnOnce<<T as Function>::Args> for Trampoline<'a, T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    type Output = <T as Function>::Output;
+
+    extern "rust-call" fn call_once(self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&self, args)
+    }
+}
+
+/// The trampoline function of a hook, keeping the hook alive.
+///
+/// This value can be called like the target function, with the arguments passed as a tuple when
+/// using `call()`. See `Hook::original()`.
+pub struct OriginalFn<T: Function>(Hook<T>);
+
+impl<T> Fn<<T as Function>::Args> for OriginalFn<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call(&self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&self.0.trampoline, args)
+    }
+}
+
+impl<T> FnMut<<T as Function>::Args> for OriginalFn<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    extern "rust-call" fn call_mut(&mut self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&*self, args)
+    }
+}
+
+impl<T> FnOnce<<T as Function>::Args> for OriginalFn<T>
+where T: Function + Fn<<T as Function>::Args, Output = <T as Function>::Output> {
+    type Output = <T as Function>::Output;
+
+    extern "rust-call" fn call_once(self, args: T::Args) -> <T as Function>::Output {
+        Fn::call(&self, args)
+    }
+}
+
+impl<T: Function> fmt::Debug for OriginalFn<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("OriginalFn").field(&self.0).finish()
+    }
+}
+
+thread_local! {
+    // The targets of the hooks for which `call_original_safely()` is in progress.
+    static ACTIVE_CALLS: RefCell<Vec<FnPointer>> = RefCell::new(Vec::new())
+}
+
+impl<T: Function> fmt::Debug for Hook<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Hook")
+           .field("signature", &T::signature())
+           .field("target", &format_args!("{:p}", self.target))
+           .field("trampoline", &format_args!("{:p}", self.trampoline.to_ptr()))
+           .finish()
+    }
+}
+
+/// Hooks are equal if they have the same target function.
+impl<T: Function, U: Function> PartialEq<Hook<U>> for Hook<T> {
+    fn eq(&self, other: &Hook<U>) -> bool {
+        self.target == other.target
+    }
+}
+
+impl<T: Function> Eq for Hook<T> {}
+
+impl<T: Function> Drop for Hook<T> {
+    fn drop(&mut self) {
+        let _ = self.release_with(&RealApi);
+    }
+}
+
+// A hook only consists of the target and trampoline code pointers, which are valid from any
+// thread. Synchronization of the operations on a hook is done in the MinHook library.
+unsafe impl<T: Function> Sync for Hook<T> {}
+unsafe impl<T: Function> Send for Hook<T> {}
+
+
+
+/// A guard that keeps a hook disabled while it is in scope.
+///
+/// This guard is created by `Hook::bypass_scoped()`.
+#[must_use]
+pub struct BypassGuard<'a, T: Function + 'a> {
+    hook: &'a Hook<T>,
+    enabled: bool
+}
+
+impl<'a, T: Function> Drop for BypassGuard<'a, T> {
+    fn drop(&mut self) {
+        if self.enabled {
+            let _ = self.hook.enable();
+        }
+    }
+}
+
+
+
+/// A guard that keeps a set of hooks enabled while it is in scope.
+///
+/// This guard is created by `Hook::apply_queue_scoped()`.
+#[must_use]
+pub struct QueueGuard<'a> {
+    targets: Vec<FnPointer>,
+    marker: PhantomData<&'a ()>
+}
+
+impl<'a> Drop for QueueGuard<'a> {
+    fn drop(&mut self) {
+        let mut queue = HookQueue(self.targets.iter().map(|&target| (target, false, 0)).collect());
+        let _ = queue.apply();
+    }
+}
+
+
+
+/// A hook that is known to be disabled.
+///
+/// This hook is created by `Hook::new_disabled()`. Enabling it consumes it and returns an
+/// `EnabledHook`, and disabling that in turn returns a `DisabledHook`, so that the state of the
+/// hook is always reflected in its type. If a state change fails the hook is dropped, and thus
+/// removed, together with the error.
+///
+/// Enabling an already enabled hook is rejected at compile time:
+///
+/// ```compile_fail
+/// # #![feature(associated_consts)]
+/// # extern crate minhook;
+/// # fn main() {
+/// fn f(x: i32) -> i32 { x }
+/// fn d(x: i32) -> i32 { -x }
+///
+/// let hook = unsafe { minhook::Hook::<fn(i32) -> i32>::new_disabled(f, d).unwrap() };
+/// let hook = hook.enable().unwrap();
+/// hook.enable();
+/// # }
+/// ```
+pub struct DisabledHook<T: Function>(Hook<T>);
+
+impl<T: Function> fmt::Debug for DisabledHook<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("DisabledHook").field(&self.0).finish()
+    }
+}
+
+impl<T: Function> DisabledHook<T> {
+    /// Enables this hook.
+    pub fn enable(self) -> HookResult<EnabledHook<T>> {
+        try!(self.0.enable());
+        Ok(EnabledHook(self.0))
+    }
+
+    /// Returns a pointer to the trampoline function.
+    ///
+    /// See `Hook::trampoline()`.
+    pub fn trampoline(&self) -> T::Unsafe {
+        self.0.trampoline()
+    }
+
+    /// Returns the underlying hook, which no longer tracks its state in its type.
+    pub fn into_inner(self) -> Hook<T> {
+        self.0
+    }
+}
+
+/// A hook that is known to be enabled.
+///
+/// This hook is created by `DisabledHook::enable()`.
+pub struct EnabledHook<T: Function>(Hook<T>);
+
+impl<T: Function> fmt::Debug for EnabledHook<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("EnabledHook").field(&self.0).finish()
+    }
+}
+
+impl<T: Function> EnabledHook<T> {
+    /// Disables this hook.
+    pub fn disable(self) -> HookResult<DisabledHook<T>> {
+        try!(self.0.disable());
+        Ok(DisabledHook(self.0))
+    }
+
+    /// Returns a pointer to the trampoline function.
+    ///
+    /// See `Hook::trampoline()`.
+    pub fn trampoline(&self) -> T::Unsafe {
+        self.0.trampoline()
+    }
+
+    /// Returns the underlying hook, which no longer tracks its state in its type.
+    pub fn into_inner(self) -> Hook<T> {
+        self.0
+    }
+}
+
+
+
+/// A function identifier used for dynamically looking up a function.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FunctionId<'a> {
+    /// The function's ordinal value.
+    Ordinal(u16),
+    /// The function's name.
+    ///
+    /// Export names are effectively always ASCII, so a name that is not ASCII results in an
+    /// `InvalidFunctionName` error.
+    Name(&'a OsStr)
+}
+
+impl<'a> FunctionId<'a> {
+    /// Create a function identifier given it's ordinal value.
+    pub fn ordinal(value: u16) -> FunctionId<'static> {
+        FunctionId::Ordinal(value)
+    }
+
+    /// Create a function identifier given it's string name.
+    pub fn name<N: ?Sized + AsRef<OsStr> + 'a>(name: &'a N) -> FunctionId<'a> {
+        FunctionId::Name(name.as_ref())
+    }
+
+    /// Create a function identifier from a string.
+    ///
+    /// A string consisting of `#` followed by a decimal number, such as `"#42"`, is interpreted
+    /// as an ordinal value. Any other string is interpreted as a name.
+    ///
+    /// Ordinal values are 16-bit, so a number that is too large results in an
+    /// `InvalidFunctionName` error instead of being interpreted as a name.
+    pub fn parse(string: &'a str) -> Result<FunctionId<'a>> {
+        if string.starts_with('#') {
+            let digits = &string[1..];
+            if !digits.is_empty() && digits.bytes().all(|c| c >= b'0' && c <= b'9') {
+                return digits.parse().map(FunctionId::Ordinal).map_err(|_| Error::InvalidFunctionName);
+            }
+        }
+        Ok(FunctionId::Name(string.as_ref()))
+    }
+}
+
+impl<'a> fmt::Display for FunctionId<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FunctionId::Ordinal(ord) => write!(fmt, "#{}", ord),
+            FunctionId::Name(name) => write!(fmt, "{}", name.to_string_lossy())
+        }
+    }
+}
+
+/// A function identifier that owns the function's name.
+///
+/// This is produced by parsing an identifier using `FromStr`, and can be borrowed as a
+/// `FunctionId` in order to look up the function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OwnedFunctionId {
+    /// The function's ordinal value.
+    Ordinal(u16),
+    /// The function's name.
+    ///
+    /// The same restrictions as for `FunctionId::Name` apply.
+    Name(String)
+}
+
+impl OwnedFunctionId {
+    /// Borrows this identifier as a `FunctionId`.
+    pub fn as_function_id(&self) -> FunctionId {
+        match *self {
+            OwnedFunctionId::Ordinal(ord) => FunctionId::Ordinal(ord),
+            OwnedFunctionId::Name(ref name) => FunctionId::name(name)
+        }
+    }
+}
+
+impl fmt::Display for OwnedFunctionId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.as_function_id(), fmt)
+    }
+}
+
+/// Parses a function identifier following the same rules as `FunctionId::parse()`.
+///
+/// Names that can not be used to look up a function result in an `InvalidFunctionName` error, or
+/// in an `InteriorNul` error if they contain a NUL character.
+impl FromStr for OwnedFunctionId {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<OwnedFunctionId> {
+        Ok(match try!(FunctionId::parse(string)) {
+            FunctionId::Ordinal(ord) => OwnedFunctionId::Ordinal(ord),
+            FunctionId::Name(_) => OwnedFunctionId::Name(try!(ascii_function_name(string.as_ref())).to_string())
+        })
+    }
+}
+
+
+/// A hook with a static lifetime.
+///
+/// This hook can only be constructed using the `static_hooks!` macro. It has one of the
 /// following forms:
 ///
 /// ```ignore
@@ -260,7 +1237,10 @@ impl<'a> FunctionId<'a> {
 pub struct StaticHook<T: Function> {
     hook: &'static AtomicInitCell<__StaticHookInner<T>>,
     target: __StaticHookTarget<T>,
-    detour: T
+    detour: T,
+    // A copy of the trampoline of `hook`, or null if it is not initialized yet. This allows the
+    // trampoline to be called without looking up the hook first.
+    trampoline: AtomicPtr<c_void>
 }
 
 impl<T: Function> StaticHook<T> {
@@ -269,31 +1249,104 @@ impl<T: Function> StaticHook<T> {
         StaticHook {
             hook: hook,
             target: target,
-            detour: detour
+            detour: detour,
+            trampoline: AtomicPtr::new(ptr::null_mut())
         }
     }
 
     /// Returns a reference to the trampoline function.
     pub fn trampoline(&self) -> T {
-        self.inner().trampoline
+        let trampoline = self.trampoline.load(Ordering::Acquire);
+        if trampoline.is_null() {
+            return self.inner().trampoline;
+        }
+        unsafe { T::from_ptr(FnPointer::from_raw(trampoline)) }
+    }
+
+    /// Returns a reference to the trampoline function, or `None` if this hook is not initialized.
+    pub fn try_trampoline(&self) -> Option<T> {
+        self.try_hook().map(|hook| hook.trampoline)
+    }
+
+    /// Returns a reference to the underlying hook, or `None` if this hook is not initialized.
+    pub fn try_hook(&self) -> Option<&'static Hook<T>> {
+        self.hook.get().map(|&__StaticHookInner(ref hook, _)| hook)
+    }
+
+    /// Returns `true` if this hook is initialized.
+    ///
+    /// Unlike most other methods, this method does not panic if the hook is uninitialized.
+    pub fn is_initialized(&self) -> bool {
+        self.hook.get().is_some()
     }
 
-    unsafe fn initialize_ref(&self, closure: &'static (Fn<T::Args, Output = T::Output> + Sync)) -> Result<()> {
-        let hook = match self.target {
-            __StaticHookTarget::Static(target) => try!(Hook::create(target, self.detour)),
+    unsafe fn create_hook(&self) -> HookResult<Hook<T>> {
+        match self.target {
+            __StaticHookTarget::Static(target) => Hook::create(target, self.detour),
             __StaticHookTarget::Dynamic(module_name, function_name) =>
-                try!(Hook::create_api(module_name, FunctionId::name(function_name), self.detour))
-        };
+                Hook::create_api(module_name, FunctionId::name(function_name), self.detour)
+        }
+    }
+
+    unsafe fn initialize_ref(&self, closure: &'static (Fn<T::Args, Output = T::Output> + Sync), enabled: bool) -> HookResult<()> {
+        let hook = try!(self.create_hook());
+
+        // The detour function assumes that the cell is initialized, so it has to be initialized
+        // before the hook is enabled.
+        let trampoline = hook.trampoline.to_ptr();
+        self.hook.initialize(__StaticHookInner(hook, closure)).expect("static hook already initialized");
+
+        if enabled {
+            if let Err(error) = self.inner().enable() {
+                // The hook was never enabled, so the detour function can not be running. Dropping
+                // the hook removes it again.
+                mem::drop(self.hook.take());
+                return Err(error);
+            }
+        }
 
-        Ok(self.hook.initialize(__StaticHookInner(hook, closure)).expect("static hook already initialized"))
+        self.trampoline.store(trampoline.to_raw(), Ordering::Release);
+        Ok(())
     }
 
-    unsafe fn initialize_box(&self, closure: Box<Fn<T::Args, Output = T::Output> + Sync>) -> Result<()> {
-        try!(self.initialize_ref(&*(&*closure as *const _)));
+    unsafe fn initialize_box(&self, closure: Box<Fn<T::Args, Output = T::Output> + Sync>) -> HookResult<()> {
+        try!(self.initialize_ref(&*(&*closure as *const _), false));
         mem::forget(closure);
         Ok(())
     }
 
+    unsafe fn reinitialize_ref(&self, closure: &'static (Fn<T::Args, Output = T::Output> + Sync)) -> HookResult<()> {
+        let old_hook = self.try_hook().expect("attempt to reinitialize uninitialized static hook");
+
+        let enabled = try!(old_hook.disable_if_enabled());
+        try!(RealApi.remove(old_hook.target)
+                    .map_err(|error| HookError::new(error, Some(old_hook.target), "remove hook")));
+        unregister(old_hook.target);
+
+        // The old hook is already removed and references to it might still be around, so it
+        // has to be leaked instead of dropped. Its trampoline is freed, so it may not be left in
+        // the cell if creating the new hook fails.
+        let hook = match self.create_hook() {
+            Ok(hook) => hook,
+            Err(error) => {
+                self.trampoline.store(ptr::null_mut(), Ordering::Release);
+                mem::forget(self.hook.take());
+                return Err(error);
+            }
+        };
+
+        // The detour function and the trampoline have to refer to the new hook before it is
+        // enabled.
+        let trampoline = hook.trampoline.to_ptr();
+        mem::forget(self.hook.replace(__StaticHookInner(hook, closure)));
+        self.trampoline.store(trampoline.to_raw(), Ordering::Release);
+
+        if enabled {
+            try!(self.inner().enable());
+        }
+        Ok(())
+    }
+
     /// Initialize and install the underlying hook using a detour closure.
     ///
     /// # Panics
@@ -303,15 +1356,83 @@ impl<T: Function> StaticHook<T> {
     /// # Safety
     ///
     /// See documentation for [`Hook::create()`](struct.Hook.html#method.create) and
-    /// [`Hook::create_api()`](struct.Hook.html#method.create_api)
-    pub unsafe fn initialize<F>(&self, closure: F) -> Result<()>
+    /// [`Hook::create_api()`](struct.Hook.html#method.create_api). If the hook was declared with
+    /// the `no_catch` keyword, the detour must never panic.
+    pub unsafe fn initialize<F>(&self, closure: F) -> HookResult<()>
     where F: Fn<T::Args, Output = T::Output> + Sync + 'static {
         self.initialize_box(Box::new(closure))
     }
 
+    /// Initialize and install the underlying hook using a reference to a detour closure.
+    ///
+    /// Unlike `initialize()`, which moves the closure into a box that is leaked, this uses the
+    /// given closure directly. This is useful for detours that are stored in a `static`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hook was already initialized.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for `initialize()`.
+    pub unsafe fn initialize_static(&self, closure: &'static (Fn<T::Args, Output = T::Output> + Sync)) -> HookResult<()> {
+        self.initialize_ref(closure, false)
+    }
+
+    /// Replace the underlying hook with a newly created hook using the given detour closure.
+    ///
+    /// This is useful when the target module got reloaded. The enabled state of the old hook is
+    /// carried over to the new hook. The old hook and its detour closure are leaked.
+    ///
+    /// If creating the new hook fails, the old hook is removed nonetheless and this hook is left
+    /// uninitialized, so that it can be initialized again using `initialize()`. If enabling the
+    /// new hook fails, it is left installed but disabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hook was not yet initialized.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for [`Hook::create()`](struct.Hook.html#method.create) and
+    /// [`Hook::create_api()`](struct.Hook.html#method.create_api)
+    pub unsafe fn reinitialize<F>(&self, closure: F) -> HookResult<()>
+    where F: Fn<T::Args, Output = T::Output> + Sync + 'static {
+        let closure: Box<Fn<T::Args, Output = T::Output> + Sync> = Box::new(closure);
+        try!(self.reinitialize_ref(&*(&*closure as *const _)));
+        mem::forget(closure);
+        Ok(())
+    }
+
+    /// Removes the underlying hook, leaving this hook uninitialized.
+    ///
+    /// Static hooks are not removed otherwise, so all initialized static hooks have to be
+    /// destroyed before `uninitialize()` can succeed, for example before a DLL containing their
+    /// detour functions is unloaded without the `libc` feature. The hook can be initialized again
+    /// afterwards. The old hook and its detour closure are leaked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hook was not yet initialized.
+    ///
+    /// # Safety
+    ///
+    /// The trampoline of the old hook is freed, so it may not be running or called anymore,
+    /// including through references to the underlying hook obtained before this call.
+    pub unsafe fn destroy(&self) -> HookResult<()> {
+        let hook = self.try_hook().expect("attempt to destroy uninitialized static hook");
+
+        try!(hook.disable_if_enabled());
+        try!(RealApi.remove(hook.target).map_err(|error| HookError::new(error, Some(hook.target), "remove hook")));
+        unregister(hook.target);
+
+        self.trampoline.store(ptr::null_mut(), Ordering::Release);
+        mem::forget(self.hook.take());
+        Ok(())
+    }
+
     fn inner(&self) -> &'static Hook<T> {
-        let &__StaticHookInner(ref hook, _) = self.hook.get().expect("attempt to access uninitialized static hook");
-        hook
+        self.try_hook().expect("attempt to access uninitialized static hook")
     }
 }
 
@@ -331,28 +1452,35 @@ impl<T: Function> Deref for StaticHook<T> {
 /// following forms:
 ///
 /// ```ignore
-/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = CLOSURE_EXPR;
-/// #[ATTR]* pub? impl HOOK_VAR_NAME for "FUNCTION" in "MODULE": FN_TYPE = CLOSURE_EXPR;
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = enabled? CLOSURE_EXPR;
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for "FUNCTION" in "MODULE": FN_TYPE = enabled? CLOSURE_EXPR;
 /// ```
 ///
 /// Before accessing this hook it is **required** to call `initialize()`. Accessing the hook
 /// before initializing or trying to initialize the hook more than once will result in a panic.
+///
+/// If the optional `enabled` keyword is used, the hook is also enabled by `initialize()`.
 pub struct StaticHookWithDefault<T: Function> {
     inner: StaticHook<T>,
     default: &'static (Fn<T::Args, Output = T::Output> + Sync),
+    enabled: bool
 }
 
 impl<T: Function> StaticHookWithDefault<T> {
     #[doc(hidden)]
-    pub const fn __new(hook: StaticHook<T>, default: &'static (Fn<T::Args, Output = T::Output> + Sync)) -> StaticHookWithDefault<T> {
+    pub const fn __new(hook: StaticHook<T>, default: &'static (Fn<T::Args, Output = T::Output> + Sync), enabled: bool) -> StaticHookWithDefault<T> {
         StaticHookWithDefault {
             inner: hook,
-            default: default
+            default: default,
+            enabled: enabled
         }
     }
 
     /// Initialize and install the underlying hook.
     ///
+    /// If the hook was declared with the `enabled` keyword, it is enabled as well. If enabling
+    /// fails, the hook is removed again and the error is returned.
+    ///
     /// # Panics
     ///
     /// Panics if the hook was already initialized.
@@ -360,9 +1488,33 @@ impl<T: Function> StaticHookWithDefault<T> {
     /// # Safety
     ///
     /// See documentation for [`Hook::create()`](struct.Hook.html#method.create) and
+    /// [`Hook::create_api()`](struct.Hook.html#method.create_api). If the hook was declared with
+    /// the `no_catch` keyword, the detour must never panic.
+    pub unsafe fn initialize(&self) -> HookResult<()> {
+        self.inner.initialize_ref(self.default, self.enabled)
+    }
+
+    /// Replace the underlying hook with a newly created hook using the default detour closure.
+    ///
+    /// This is useful when the target module got reloaded. The enabled state of the old hook is
+    /// carried over to the new hook. The old hook is leaked. Use
+    /// [`StaticHook::reinitialize()`](struct.StaticHook.html#method.reinitialize) through
+    /// dereferencing to use a different detour closure instead.
+    ///
+    /// If creating the new hook fails, the old hook is removed nonetheless and this hook is left
+    /// uninitialized, so that it can be initialized again using `initialize()`. If enabling the
+    /// new hook fails, it is left installed but disabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hook was not yet initialized.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for [`Hook::create()`](struct.Hook.html#method.create) and
     /// [`Hook::create_api()`](struct.Hook.html#method.create_api)
-    pub unsafe fn initialize(&self) -> Result<()> {
-        self.inner.initialize_ref(self.default)
+    pub unsafe fn reinitialize(&self) -> HookResult<()> {
+        self.inner.reinitialize_ref(self.default)
     }
 }
 
@@ -376,27 +1528,406 @@ impl<T: Function> Deref for StaticHookWithDefault<T> {
 
 
 
-fn initialize() -> Result<()> {
-    // Clean-up is *required* in DLLs. If a DLL gets unloaded while static hooks are installed
-    // the hook instructions will point to detour functions that are already unloaded.
-    extern "C" fn cleanup() {
-        let _ = unsafe { ffi::MH_Uninitialize() };
+/// Looks up the address of an exported function in a loaded module.
+///
+/// The module has to be loaded before this function is called. This function does not
+/// attempt to load the module first.
+pub fn resolve<M: AsRef<OsStr>>(module: M, function: FunctionId) -> Result<FnPointer> {
+    let handle = try!(module_handle(module.as_ref()));
+    let (function_name, _data) = try!(function_id_to_raw(&function));
+
+    unsafe {
+        let address = kernel32::GetProcAddress(handle, function_name);
+        if address.is_null() {
+            return Err(Error::FunctionNotFound);
+        }
+
+        Ok(FnPointer::from_raw(address as *mut _))
+    }
+}
+
+
+/// Looks up the address of an exported function in a loaded module by reading its export table,
+/// following any forwarders to the module that actually implements the function.
+///
+/// `GetProcAddress`, which is used by `resolve()` and `Hook::create_api()`, follows forwarders
+/// as well. Unlike that function, this function is not affected by hooks placed on
+/// `GetProcAddress` itself. All modules involved have to be loaded before this function is
+/// called. This function does not attempt to load them first.
+pub fn resolve_forwarded<M: AsRef<OsStr>>(module: M, function: FunctionId) -> Result<FnPointer> {
+    export::resolve_forwarded(module.as_ref(), function)
+}
+
+/// Checks whether MinHook is likely to be able to patch the function at the given address.
+///
+/// This decodes the instructions at the start of the function with the same disassembler that
+/// MinHook uses. It returns a `FunctionTooSmall` error if the function returns or jumps away
+/// before there is room for the 5 byte jump and it can not be hot-patched either, and an
+/// `InvalidInstruction` error if the instructions can not be decoded. Passing this check does
+/// not guarantee that creating a hook succeeds, since MinHook may still reject other
+/// instructions during relocation.
+///
+/// # Safety
+///
+/// The target address must point to the start of a function. The instructions at the start of
+/// the function are decoded without checking whether they extend past the memory region that
+/// contains the target address.
+pub unsafe fn can_hook(target: FnPointer) -> Result<()> {
+    try!(check_executable(target));
+    patch::check_patchable(target)
+}
+
+
+
+// A hook that is alive, together with everything needed to hand out another `Hook` for it.
+struct Registered {
+    target: FnPointer,
+    trampoline: FnPointer,
+    detour: FnPointer,
+    original: [u8; patch::SAVED_BYTES],
+    handles: usize,
+    enabled: bool
+}
+
+#[cfg(feature = "lazy_static")]
+lazy_static! {
+    static ref REGISTRY: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+}
+
+#[cfg(not(feature = "lazy_static"))]
+static REGISTRY: sync::SpinLock<Vec<Registered>> = sync::SpinLock::new();
+
+/// Returns the target functions of all hooks that are currently alive.
+///
+/// This includes initialized static hooks. The order of the targets is unspecified.
+pub fn all_hooks() -> Vec<FnPointer> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner).iter().map(|entry| entry.target).collect()
+}
+
+/// Disables all hooks whose target function lies in the given loaded module at once, returning
+/// the number of hooks that were disabled.
+///
+/// This is useful before unloading a module. Hooks that are already disabled are not included in
+/// the count. The module has to be loaded before this function is called. This function does not
+/// attempt to load the module first.
+pub fn disable_module<M: AsRef<OsStr>>(module: M) -> HookResult<usize> {
+    let handle = try!(module_handle(module.as_ref())
+                          .map_err(|error| HookError::new(error, None, "find target module")));
+
+    let mut queue = HookQueue::new();
+    for target in all_hooks() {
+        if owning_module(target) == Some(handle) {
+            queue.disable_ptr(target);
+        }
+    }
+    queue.apply_and_report().map(|report| report.disabled)
+}
+
+// The number of registered hooks that are enabled. It is only changed while the registry is
+// locked, so that it always matches the registry.
+static ENABLED_HOOKS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the number of hooks that are currently alive and enabled.
+///
+/// This is cheap to call, since the count is kept up to date as hooks are enabled, disabled and
+/// destroyed. Only hooks managed by this library are counted, which excludes hooks created
+/// through the `capi` module.
+pub fn enabled_hook_count() -> usize {
+    ENABLED_HOOKS.load(Ordering::SeqCst)
+}
+
+// The number of hooks that are being created but are not registered yet. It is only changed while
+// the registry is locked, so that `uninitialize()` can take it into account.
+static CREATING: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Counts a hook as being created until it is dropped.
+struct Creating;
+
+impl Creating {
+    fn begin() -> Creating {
+        let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+        CREATING.fetch_add(1, Ordering::SeqCst);
+        Creating
+    }
+}
+
+impl Drop for Creating {
+    fn drop(&mut self) {
+        let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+        CREATING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn register<T: Function>(hook: Hook<T>) -> Hook<T> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner).push(Registered {
+        target: hook.target,
+        trampoline: hook.trampoline.to_ptr(),
+        detour: hook.detour,
+        original: hook.original,
+        handles: 1,
+        enabled: false
+    });
+    hook
+}
+
+// Returns another handle to the hook for the given target and detour, if there is one.
+unsafe fn acquire<T: Function>(target: FnPointer, detour: FnPointer) -> Option<Hook<T>> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.iter_mut().find(|entry| entry.target == target && entry.detour == detour).map(|entry| {
+        entry.handles += 1;
+        Hook {
+            target: target,
+            trampoline: T::from_ptr(entry.trampoline),
+            detour: detour,
+            original: entry.original
+        }
+    })
+}
+
+// Returns whether the hook for the given target was last enabled successfully.
+fn is_enabled(target: FnPointer) -> bool {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner).iter().any(|entry| entry.target == target && entry.enabled)
+}
+
+// Records the state of the hook for the given target after it was changed successfully. Returns
+// whether the state of the hook actually changed.
+fn set_enabled(target: FnPointer, enabled: bool) -> bool {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = registry.iter_mut().find(|entry| entry.target == target) {
+        if entry.enabled != enabled {
+            entry.enabled = enabled;
+            if enabled {
+                ENABLED_HOOKS.fetch_add(1, Ordering::SeqCst);
+            } else {
+                ENABLED_HOOKS.fetch_sub(1, Ordering::SeqCst);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+// Returns whether the last handle was released, in which case the hook has to be removed.
+fn unregister(target: FnPointer) -> bool {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let index = registry.iter().position(|entry| entry.target == target);
+    match index {
+        Some(index) if registry[index].handles > 1 => {
+            registry[index].handles -= 1;
+            false
+        }
+        Some(index) => {
+            if registry.swap_remove(index).enabled {
+                ENABLED_HOOKS.fetch_sub(1, Ordering::SeqCst);
+            }
+            true
+        }
+        None => false
     }
+}
+
+
+
+// Defines `MINHOOK_VERSION` and `MINHOOK_ARCH`.
+include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
+/// Returns the version of the bundled MinHook library.
+pub fn minhook_version() -> &'static str {
+    MINHOOK_VERSION
+}
 
+/// Returns the target architecture the bundled MinHook library and its disassembler were compiled
+/// for.
+pub fn minhook_arch() -> &'static str {
+    MINHOOK_ARCH
+}
+
+
+
+/// Initializes the MinHook library.
+///
+/// All functions that create hooks call this function themselves, so calling it is only
+/// required if the MinHook library is used directly. Calling this function when MinHook is
+/// already initialized has no effect.
+///
+/// The first successful initialization registers an `atexit` handler that uninitializes MinHook,
+/// which removes all hooks, when the process exits or the containing DLL is unloaded. Without the
+/// `libc` feature no handler is registered, and `uninitialize()` has to be called explicitly
+/// before a DLL that contains detour functions is unloaded.
+pub fn initialize() -> Result<()> {
     unsafe {
-        s2r(ffi::MH_Initialize()).map(|_| {
-            libc::atexit(cleanup);
-        }).or_else(|error| match error {
+        s2r(ffi::MH_Initialize()).map(|_| register_cleanup()).or_else(|error| match error {
             Error::AlreadyInitialized => Ok(()),
             error => Err(error)
         })
     }
 }
 
+#[cfg(feature = "libc")]
+fn register_cleanup() {
+    use std::sync::atomic::{ATOMIC_BOOL_INIT, AtomicBool};
+
+    // Clean-up is *required* in DLLs. If a DLL gets unloaded while static hooks are installed
+    // the hook instructions will point to detour functions that are already unloaded.
+    extern "C" fn cleanup() {
+        let _ = unsafe { ffi::MH_Uninitialize() };
+    }
+
+    static CLEANUP_REGISTERED: AtomicBool = ATOMIC_BOOL_INIT;
+
+    if !CLEANUP_REGISTERED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::atexit(cleanup); }
+    }
+}
+
+#[cfg(not(feature = "libc"))]
+fn register_cleanup() {}
+
+/// Uninitializes the MinHook library, removing all hooks.
+///
+/// This fails with `HooksAlive` if any hook created by this library is still alive or is being
+/// created by another thread, since its trampoline would be freed. Initialized static hooks are
+/// alive until they are destroyed using `StaticHook::destroy()`. MinHook is initialized again the
+/// next time a hook is created. The `atexit` handler registered by `initialize()` is not
+/// affected.
+///
+/// # Safety
+///
+/// Hooks created by using the MinHook library directly are removed as well, and any remaining
+/// pointers to their trampolines become invalid.
+pub unsafe fn uninitialize() -> Result<()> {
+    // The registry stays locked until MinHook is uninitialized, so that no other thread can start
+    // creating a hook in the meantime.
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    uninitialize_with(registry.len() + CREATING.load(Ordering::SeqCst), &RealApi)
+}
+
+// Takes the number of hooks that are alive or being created.
+fn uninitialize_with(alive: usize, api: &MinHookApi) -> Result<()> {
+    let result = if alive == 0 {
+        api.uninitialize()
+    } else {
+        Err(Error::HooksAlive)
+    };
+    log_result!(&result, "uninitialize with {} hooks alive", alive);
+    result
+}
+
+// Writes the given code to the relay at the given address, making it writable for the duration of
+// the write.
+unsafe fn patch_relay(relay: FnPointer, code: &[u8; patch::RELAY_SIZE]) -> Result<()> {
+    let (address, size) = (relay.to_raw(), patch::RELAY_SIZE as winapi::SIZE_T);
+    let mut protect = 0;
+    if kernel32::VirtualProtect(address, size, winapi::PAGE_EXECUTE_READWRITE, &mut protect) == 0 {
+        return Err(Error::MemoryProtect);
+    }
+    ptr::copy_nonoverlapping(code.as_ptr(), address as *mut u8, code.len());
+    let restored = kernel32::VirtualProtect(address, size, protect, &mut protect) != 0;
+    kernel32::FlushInstructionCache(kernel32::GetCurrentProcess(), address as winapi::LPCVOID, size);
+    if !restored {
+        return Err(Error::MemoryProtect);
+    }
+    Ok(())
+}
+
 fn s2r(status: ffi::MH_STATUS) -> Result<()> {
     Error::from_status(status).map_or(Ok(()), Err)
 }
 
+fn retry<R, F>(attempts: u32, delay: Duration, mut f: F) -> Result<R>
+where F: FnMut() -> Result<R> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(Error::UnsupportedFunction) | Err(Error::MemoryProtect) if attempt < attempts => {
+                attempt += 1;
+                thread::sleep(delay);
+            }
+            result => return result
+        }
+    }
+}
+
+fn module_handle(name: &OsStr) -> Result<winapi::HMODULE> {
+    let module_name = try!(str_to_wstring(name));
+
+    let handle = unsafe { kernel32::GetModuleHandleW(module_name.as_ptr()) };
+    if handle.is_null() {
+        return Err(Error::ModuleNotFound);
+    }
+    Ok(handle)
+}
+
+// The base address of the allocation containing the address, which for code in a module is the
+// module handle.
+fn owning_module(address: FnPointer) -> Option<winapi::HMODULE> {
+    let mut info: winapi::MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+    let size = mem::size_of::<winapi::MEMORY_BASIC_INFORMATION>() as winapi::SIZE_T;
+    if unsafe { kernel32::VirtualQuery(address.to_raw() as winapi::LPCVOID, &mut info, size) } == 0 ||
+       info.State != winapi::MEM_COMMIT {
+        return None;
+    }
+    Some(info.AllocationBase as winapi::HMODULE)
+}
+
+// MinHook only reports `NotExecutable` for these addresses, so look up the protection of the
+// region beforehand in order to report it.
+fn check_executable(address: FnPointer) -> Result<()> {
+    const EXECUTE_FLAGS: winapi::DWORD = winapi::PAGE_EXECUTE | winapi::PAGE_EXECUTE_READ |
+                                         winapi::PAGE_EXECUTE_READWRITE | winapi::PAGE_EXECUTE_WRITECOPY;
+
+    let mut info: winapi::MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+    let size = mem::size_of::<winapi::MEMORY_BASIC_INFORMATION>() as winapi::SIZE_T;
+    if unsafe { kernel32::VirtualQuery(address.to_raw() as winapi::LPCVOID, &mut info, size) } == 0 {
+        return Err(Error::NotExecutableDetail { protect: 0 });
+    }
+
+    if info.State != winapi::MEM_COMMIT {
+        Err(Error::NotExecutableDetail { protect: 0 })
+    } else if info.Protect & EXECUTE_FLAGS == 0 || info.Protect & winapi::PAGE_GUARD != 0 {
+        Err(Error::NotExecutableDetail { protect: info.Protect })
+    } else {
+        Ok(())
+    }
+}
+
+fn str_to_wstring(string: &OsStr) -> Result<Vec<winapi::WCHAR>> {
+    let mut wide = string.encode_wide().collect::<Vec<_>>();
+    if wide.contains(&0) {
+        return Err(Error::InteriorNul);
+    }
+    wide.push(0);
+    Ok(wide)
+}
+
+// The returned pointer points into the returned buffer (if any), so the buffer has to be kept
+// alive for as long as the pointer is used.
+//
+// Export names are stored as raw bytes in the export table and are ASCII in practice. Names are
+// passed on as is instead of being converted to the ANSI code page, which would depend on the
+// system locale, so names that are not ASCII are rejected.
+fn ascii_function_name(name: &OsStr) -> Result<&str> {
+    let name = try!(name.to_str().ok_or(Error::InvalidFunctionName));
+    if name.contains('\0') {
+        return Err(Error::InteriorNul);
+    }
+    if name.is_empty() || !name.bytes().all(|c| c < 0x80) {
+        return Err(Error::InvalidFunctionName);
+    }
+    Ok(name)
+}
+
+fn function_id_to_raw(id: &FunctionId) -> Result<(winapi::LPCSTR, Vec<winapi::CHAR>)> {
+    let name = match *id {
+        FunctionId::Ordinal(ord) => return Ok((ord as winapi::LPCSTR, Vec::new())),
+        FunctionId::Name(name) => try!(ascii_function_name(name))
+    };
+
+    let mut buffer = name.bytes().map(|c| c as winapi::CHAR).collect::<Vec<_>>();
+    buffer.push(0);
+    Ok((buffer.as_ptr(), buffer))
+}
+
 
 
 #[doc(hidden)]
@@ -413,15 +1944,17 @@ pub enum __StaticHookTarget<T: Function> {
 
 #[cfg(test)]
 mod tests {
-    use std::mem;
+    use std::{mem, ptr, slice};
     use std::sync::Mutex;
     use std::ffi::OsStr;
     use std::os::windows::ffi::OsStrExt;
     use std::os::raw::c_int;
 
     use {winapi, kernel32};
+    use test::{self, Bencher};
 
     use super::*;
+    use function::{Function, FnPointer};
 
     #[test]
     fn local() {
@@ -565,4 +2098,2184 @@ mod tests {
         assert_eq!(f2(42), 84);
         assert_eq!(f3(-10), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn queue_deduplicate() {
+        fn f1(x: i32) -> i32 { x }
+        fn d1(x: i32) -> i32 { -x }
+
+        fn f2(x: i32) -> i32 { x * 2 }
+        fn d2(x: i32) -> i32 { x + 2 }
+
+        let (h1, h2) = unsafe { (
+            Hook::<fn(i32) -> i32>::create(f1, d1).unwrap(),
+            Hook::<fn(i32) -> i32>::create(f2, d2).unwrap()
+        ) };
+
+        let mut queue = HookQueue::new();
+        queue.enable(&h1)
+             .enable(&h2)
+             .disable(&h1);
+
+        assert_eq!(queue.deduplicated(), vec![(h1.target, false), (h2.target, true)]);
+
+        queue.apply().unwrap();
+
+        assert_eq!(f1(42), 42);
+        assert_eq!(f2(42), 44);
+    }
+
+    #[test]
+    fn error_classification() {
+        assert!(Error::AlreadyCreated.is_already_state());
+        assert!(Error::Disabled.is_already_state());
+        assert!(!Error::NotCreated.is_already_state());
+
+        assert!(Error::ModuleNotFound.is_not_found());
+        assert!(Error::FunctionNotFound.is_not_found());
+        assert!(!Error::InvalidFunctionName.is_not_found());
+
+        assert!(Error::UnsupportedFunction.is_target_unhookable());
+        assert!(Error::NotExecutable.is_target_unhookable());
+        assert!(!Error::NotInitialized.is_target_unhookable());
+
+        assert!(Error::MemoryAlloc.is_transient());
+        assert!(!Error::AlreadyEnabled.is_transient());
+    }
+
+    // Exhausting the address space near a target function is not feasible in a test, so only the
+    // message is checked.
+    #[test]
+    fn error_memory_alloc_message() {
+        let message = Error::MemoryAlloc.to_string();
+        assert!(message.starts_with("Failed to allocate memory"));
+        if cfg!(target_arch = "x86_64") {
+            assert!(message.contains("within 1 GB of the target function"));
+        }
+    }
+
+    #[test]
+    fn error_into_io_error() {
+        use std::io;
+
+        let error = io::Error::from(Error::FunctionNotFound);
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), Error::FunctionNotFound.to_string());
+
+        assert_eq!(io::Error::from(Error::InvalidModuleName).kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(io::Error::from(Error::MemoryAlloc).kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn resolve_export() {
+        let foo = OsStr::new("foo").encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        let lstrlen_w = resolve("kernel32.dll", FunctionId::name("lstrlenW")).unwrap();
+        unsafe {
+            let lstrlen_w: extern "system" fn(winapi::LPCWSTR) -> c_int = mem::transmute(lstrlen_w.to_raw());
+            assert_eq!(lstrlen_w(foo.as_ptr()), kernel32::lstrlenW(foo.as_ptr()));
+        }
+
+        assert_eq!(resolve("does_not_exist.dll", FunctionId::name("lstrlenW")), Err(Error::ModuleNotFound));
+        assert_eq!(resolve("kernel32.dll", FunctionId::name("DoesNotExist")), Err(Error::FunctionNotFound));
+    }
+
+    #[test]
+    fn module_exports() {
+        let module = Module::load("kernel32.dll").unwrap();
+        let exports = module.exports().unwrap();
+
+        let lstrlen_w = exports.iter().find(|export| export.name.as_ref().map_or(false, |name| name == "lstrlenW")).unwrap();
+        assert!(!lstrlen_w.address.to_raw().is_null());
+        assert_eq!(lstrlen_w.address, resolve("kernel32.dll", FunctionId::name("lstrlenW")).unwrap());
+        assert_eq!(lstrlen_w.address, resolve("kernel32.dll", FunctionId::ordinal(lstrlen_w.ordinal)).unwrap());
+    }
+
+    #[test]
+    fn create_api_ordinal() {
+        type LstrcmpiA = extern "system" fn(winapi::LPCSTR, winapi::LPCSTR) -> c_int;
+
+        extern "system" fn lstrcmpi_a_detour(_string1: winapi::LPCSTR, _string2: winapi::LPCSTR) -> c_int {
+            197
+        }
+
+        let module = Module::load("kernel32.dll").unwrap();
+        let lstrcmpi_a = module.exports().unwrap().into_iter()
+                               .find(|export| export.name.as_ref().map_or(false, |name| name == "lstrcmpiA"))
+                               .unwrap();
+
+        let foo = b"foo\0".as_ptr() as winapi::LPCSTR;
+        unsafe {
+            let h = Hook::<LstrcmpiA>::create_api_ordinal_typed("kernel32.dll", lstrcmpi_a.ordinal, lstrcmpi_a_detour).unwrap();
+            h.enable().unwrap();
+            assert_eq!(kernel32::lstrcmpiA(foo, foo), 197);
+            assert_eq!(h.call_real(foo, foo), 0);
+            h.disable().unwrap();
+            assert_eq!(kernel32::lstrcmpiA(foo, foo), 0);
+        }
+    }
+
+    #[test]
+    fn function_name_ascii() {
+        use super::function_id_to_raw;
+
+        // ASCII names are passed on as is, independent of the ANSI code page.
+        let (name, buffer) = function_id_to_raw(&FunctionId::name("lstrlenW")).unwrap();
+        assert_eq!(name, buffer.as_ptr());
+        assert_eq!(buffer.iter().map(|&c| c as u8).collect::<Vec<_>>(), b"lstrlenW\0".to_vec());
+
+        // The buffer is built from initialized bytes only, whatever the kind of identifier.
+        let (name, buffer) = function_id_to_raw(&"lstrlenA".parse::<OwnedFunctionId>().unwrap().as_function_id()).unwrap();
+        assert_eq!(name, buffer.as_ptr());
+        assert_eq!(buffer.iter().map(|&c| c as u8).collect::<Vec<_>>(), b"lstrlenA\0".to_vec());
+        let (name, buffer) = function_id_to_raw(&FunctionId::ordinal(42)).unwrap();
+        assert_eq!(name as usize, 42);
+        assert!(buffer.is_empty());
+
+        assert_eq!(resolve("kernel32.dll", FunctionId::name("lstrlen\u{e9}")), Err(Error::InvalidFunctionName));
+        assert_eq!(resolve_forwarded("kernel32.dll", FunctionId::name("lstrlen\u{e9}")), Err(Error::InvalidFunctionName));
+        assert_eq!(resolve("kernel32.dll", FunctionId::name("")), Err(Error::InvalidFunctionName));
+    }
+
+    #[test]
+    fn real_api_error_path() {
+        use api::{MinHookApi, RealApi};
+
+        fn d(x: i32) -> i32 { x * 439 }
+
+        initialize().unwrap();
+        let module = str_to_wstring(OsStr::new("kernel32.dll")).unwrap();
+        unsafe {
+            let memory = kernel32::VirtualAlloc(ptr::null_mut(), 4096, winapi::MEM_COMMIT | winapi::MEM_RESERVE,
+                                                winapi::PAGE_READWRITE);
+            assert!(!memory.is_null());
+            let detour = FnPointer::of(d as fn(i32) -> i32);
+            assert_eq!(RealApi.create(FnPointer::from_raw(memory), detour), Err(Error::NotExecutable));
+            assert_eq!(RealApi.create_api(module.as_ptr(), b"DoesNotExist\0".as_ptr() as winapi::LPCSTR, detour),
+                       Err(Error::FunctionNotFound));
+            kernel32::VirtualFree(memory, 0, winapi::MEM_RELEASE);
+        }
+    }
+
+    #[test]
+    fn ordinal_out_of_range() {
+        type LstrlenA = extern "system" fn(winapi::LPCSTR) -> c_int;
+        extern "system" fn d(_: winapi::LPCSTR) -> c_int { 0 }
+
+        let error = unsafe { Hook::<LstrlenA>::create_api_loading("kernel32.dll", FunctionId::ordinal(65535), d) }.unwrap_err();
+        match error.source {
+            Error::OrdinalOutOfRange { ordinal, base, count } => {
+                assert_eq!(ordinal, 65535);
+                assert!(count > 0 && base + count <= 65535);
+            }
+            error => panic!("unexpected error: {}", error)
+        }
+        assert!(error.source.is_not_found());
+        assert_eq!(error.context, "resolve target function");
+    }
+
+    #[test]
+    fn interior_nul() {
+        type LstrlenA = extern "system" fn(winapi::LPCSTR) -> c_int;
+        extern "system" fn d(_: winapi::LPCSTR) -> c_int { 0 }
+
+        assert_eq!(resolve("kernel32\0.dll", FunctionId::name("lstrlenA")), Err(Error::InteriorNul));
+        assert_eq!(Module::load("kernel32\0.dll").unwrap_err(), Error::InteriorNul);
+        assert_eq!(resolve("kernel32.dll", FunctionId::name("lstrlen\0A")), Err(Error::InteriorNul));
+        assert_eq!("lstrlen\0A".parse::<OwnedFunctionId>(), Err(Error::InteriorNul));
+
+        unsafe {
+            let error = Hook::<LstrlenA>::create_api("kernel32\0.dll", FunctionId::name("lstrlenA"), d).unwrap_err();
+            assert_eq!(error, Error::InteriorNul);
+            assert_eq!(error.context, "create hook for exported function");
+            let error = Hook::<LstrlenA>::create_api("kernel32.dll", FunctionId::name("lstrlen\0A"), d).unwrap_err();
+            assert_eq!(error, Error::InteriorNul);
+        }
+    }
+
+    #[test]
+    fn static_named_detour() {
+        fn f(x: i32, y: i32) -> i32 { x + y }
+        fn d(x: i32, _y: i32) -> i32 { h.call_real(x, 2) }
+
+        static_hooks! {
+            impl h for f: fn(i32, i32) -> i32 = d;
+        }
+
+        assert_eq!(f(3, 6), 9);
+        unsafe { h.initialize().unwrap(); }
+        assert_eq!(f(3, 6), 9);
+        h.enable().unwrap();
+        assert_eq!(f(3, 6), 5);
+        assert_eq!(f(3, 66), 5);
+        h.disable().unwrap();
+        assert_eq!(f(3, 6), 9);
+    }
+
+    #[test]
+    fn static_enabled() {
+        fn f(x: i32) -> i32 { x }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = enabled |x| x + 1;
+        }
+
+        assert_eq!(f(1), 1);
+        unsafe { h.initialize().unwrap(); }
+        assert_eq!(f(1), 2);
+        h.disable().unwrap();
+        assert_eq!(f(1), 1);
+    }
+
+    #[test]
+    fn static_reinitialize() {
+        fn f(x: i32) -> i32 { x }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32;
+        }
+
+        unsafe { h.initialize(|x| x + 1).unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 2);
+        unsafe { h.reinitialize(|x| x + 2).unwrap(); }
+        assert_eq!(f(1), 3);
+        h.disable().unwrap();
+        assert_eq!(f(1), 1);
+    }
+
+    #[test]
+    fn static_destroy() {
+        fn f(x: i32) -> i32 { x * 467 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = enabled |x| x * 479;
+        }
+
+        let target = FnPointer::of(f as fn(i32) -> i32);
+        unsafe { h.initialize().unwrap(); }
+        assert_eq!(f(1), 479);
+        assert!(all_hooks().contains(&target));
+
+        unsafe { h.destroy().unwrap(); }
+        assert_eq!(f(1), 467);
+        assert!(!h.is_initialized());
+        assert!(!all_hooks().contains(&target));
+
+        unsafe { h.initialize().unwrap(); }
+        assert_eq!(f(1), 479);
+    }
+
+    #[test]
+    fn static_initialize_static() {
+        fn f(x: i32) -> i32 { x * 359 }
+        fn d(x: i32) -> i32 { x * 367 }
+
+        static DETOUR: fn(i32) -> i32 = d;
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32;
+        }
+
+        unsafe { h.initialize_static(&DETOUR).unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 367);
+        assert_eq!(h.call_real(1), 359);
+    }
+
+    #[test]
+    fn static_reinitialize_default() {
+        fn f(x: i32) -> i32 { x * 181 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| h.call_real(x) + 1;
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 182);
+        unsafe { h.reinitialize().unwrap(); }
+        assert_eq!(f(1), 182);
+        h.disable().unwrap();
+        assert_eq!(f(1), 181);
+
+        unsafe { h.reinitialize().unwrap(); }
+        assert_eq!(f(1), 181);
+        h.enable().unwrap();
+        assert_eq!(f(1), 182);
+        h.disable().unwrap();
+    }
+
+    #[test]
+    fn cell_publish_threads() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+
+        static CELL: AtomicInitCell<[u64; 16]> = AtomicInitCell::new();
+
+        let start = Arc::new(AtomicBool::new(false));
+        let readers = (0..8).map(|_| {
+            let start = start.clone();
+            thread::spawn(move || {
+                while !start.load(Ordering::Relaxed) {}
+                loop {
+                    if let Some(values) = CELL.get() {
+                        assert!(values.iter().all(|&value| value == 0x0123_4567_89ab_cdef));
+                        return;
+                    }
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        start.store(true, Ordering::Relaxed);
+        assert_eq!(CELL.initialize([0x0123_4567_89ab_cdef; 16]), Ok(()));
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn cell_get_unchecked() {
+        static CELL: AtomicInitCell<i32> = AtomicInitCell::new();
+
+        assert_eq!(CELL.initialize(4), Ok(()));
+        assert_eq!(unsafe { CELL.get_unchecked() }, &4);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "uninitialized")]
+    fn cell_get_unchecked_uninitialized() {
+        static CELL: AtomicInitCell<i32> = AtomicInitCell::new();
+
+        unsafe { CELL.get_unchecked(); }
+    }
+
+    #[test]
+    fn cell_replace_take() {
+        static CELL: AtomicInitCell<i32> = AtomicInitCell::new();
+
+        unsafe {
+            assert_eq!(CELL.replace(1), None);
+            assert_eq!(CELL.get(), Some(&1));
+            assert_eq!(CELL.replace(2), Some(Box::new(1)));
+            assert_eq!(CELL.get(), Some(&2));
+            assert_eq!(CELL.take(), Some(Box::new(2)));
+            assert_eq!(CELL.get(), None);
+            assert_eq!(CELL.take(), None);
+        }
+        assert_eq!(CELL.initialize(3), Ok(()));
+        assert_eq!(CELL.get(), Some(&3));
+    }
+
+    #[test]
+    fn local_rva() {
+        extern "system" fn lstrcmp_w_detour(_string1: winapi::LPCWSTR, _string2: winapi::LPCWSTR) -> c_int {
+            42
+        }
+
+        let foo = OsStr::new("foo").encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        let target = resolve("kernel32.dll", FunctionId::name("lstrcmpW")).unwrap();
+        let kernel32_name = OsStr::new("kernel32.dll").encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        unsafe {
+            let base = kernel32::GetModuleHandleW(kernel32_name.as_ptr());
+            let rva = target.to_raw() as usize - base as usize;
+
+            let h = Hook::<extern "system" fn(winapi::LPCWSTR, winapi::LPCWSTR) -> c_int>::create_rva(
+                "kernel32.dll",
+                rva,
+                lstrcmp_w_detour).unwrap();
+            assert_eq!(h.target, target);
+            assert_eq!(kernel32::lstrcmpW(foo.as_ptr(), foo.as_ptr()), 0);
+            h.enable().unwrap();
+            assert_eq!(kernel32::lstrcmpW(foo.as_ptr(), foo.as_ptr()), 42);
+            h.disable().unwrap();
+            assert_eq!(kernel32::lstrcmpW(foo.as_ptr(), foo.as_ptr()), 0);
+
+            assert_eq!(Hook::<extern "system" fn(winapi::LPCWSTR, winapi::LPCWSTR) -> c_int>::create_rva(
+                "does_not_exist.dll",
+                rva,
+                lstrcmp_w_detour).unwrap_err(), Error::ModuleNotFound);
+        }
+    }
+
+    #[test]
+    fn diverging_detour() {
+        fn f() -> i32 { 42 }
+        fn d() -> ! { panic!("detour called") }
+
+        let h = unsafe { Hook::<fn() -> i32>::create_diverging(f, d).unwrap() };
+        assert_eq!(f(), 42);
+        mem::drop(h);
+    }
+
+    #[test]
+    fn local_remove() {
+        fn f(x: i32) -> i32 { x * 2 }
+        fn d(x: i32) -> i32 { x * 3 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        h.enable().unwrap();
+        assert_eq!(f(5), 15);
+        assert_eq!(h.remove().unwrap(), (f as fn(i32) -> i32).to_ptr());
+        assert_eq!(f(5), 10);
+    }
+
+    #[test]
+    fn queue_mock_api() {
+        use api::MockApi;
+
+        let (a, b, c) = unsafe { (FnPointer::from_addr(0x1000), FnPointer::from_addr(0x2000), FnPointer::from_addr(0x3000)) };
+        let mut queue = HookQueue(vec![(a, true, 0), (b, true, 0), (a, false, 0), (c, false, 0)]);
+
+        let api = MockApi::default();
+        queue.apply_with(&api).unwrap();
+        assert_eq!(api.calls(), vec![
+            ("queue_disable", Some(a)),
+            ("queue_enable", Some(b)),
+            ("queue_disable", Some(c)),
+            ("apply_queued", None)
+        ]);
+
+        let api = MockApi { fail_apply: Some(Error::NotExecutable), ..MockApi::default() };
+        assert_eq!(queue.apply_with(&api).unwrap_err(), Error::NotExecutable);
+
+        // The hook for `b` was destroyed after the queue was checked. The change that was already
+        // queued for `a` is reverted and nothing is applied.
+        let api = MockApi { not_created: vec![b], ..MockApi::default() };
+        assert_eq!(queue.apply_with(&api).unwrap_err(), HookError::new(Error::NotCreated, Some(b), "queue hook change"));
+        assert_eq!(api.calls(), vec![
+            ("queue_disable", Some(a)),
+            ("queue_enable", Some(b)),
+            ("queue_disable", Some(a))
+        ]);
+    }
+
+    #[cfg(not(feature = "lazy_static"))]
+    #[test]
+    fn spinlock_create_enable_disable() {
+        fn f(x: i32) -> i32 { x * 137 }
+        fn d(x: i32) -> i32 { x * 139 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        assert!(all_hooks().contains(&FnPointer::of(f as fn(i32) -> i32)));
+        h.enable().unwrap();
+        assert_eq!(f(1), 139);
+        h.disable().unwrap();
+        assert_eq!(f(1), 137);
+        HookQueue::new().enable(&h).apply().unwrap();
+        assert_eq!(f(1), 139);
+        mem::drop(h);
+        assert_eq!(f(1), 137);
+        assert!(!all_hooks().contains(&FnPointer::of(f as fn(i32) -> i32)));
+    }
+
+    #[test]
+    fn ffi_create_hook_api_ex() {
+        use ffi;
+
+        extern "system" fn lstrcmp_a_detour(_string1: winapi::LPCSTR, _string2: winapi::LPCSTR) -> c_int {
+            163
+        }
+
+        initialize().unwrap();
+
+        let module_name = OsStr::new("kernel32.dll").encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        let mut trampoline = ptr::null_mut();
+        let mut target = ptr::null_mut();
+        unsafe {
+            let status = ffi::MH_CreateHookApiEx(module_name.as_ptr(),
+                                                 b"lstrcmpA\0".as_ptr() as winapi::LPCSTR,
+                                                 lstrcmp_a_detour as *mut _,
+                                                 &mut trampoline,
+                                                 &mut target);
+            assert_eq!(status, ffi::MH_STATUS::MH_OK);
+            assert!(!trampoline.is_null());
+            assert_eq!(FnPointer::from_raw(target), resolve("kernel32.dll", FunctionId::name("lstrcmpA")).unwrap());
+            assert_eq!(ffi::MH_RemoveHook(target), ffi::MH_STATUS::MH_OK);
+        }
+    }
+
+    #[test]
+    fn create_target_is_detour() {
+        fn f(x: i32) -> i32 { x * 157 }
+
+        assert_eq!(unsafe { Hook::<fn(i32) -> i32>::create(f, f) }.unwrap_err(), Error::TargetIsDetour);
+        assert_eq!(f(1), 157);
+
+        type LstrcmpiW = extern "system" fn(winapi::LPCWSTR, winapi::LPCWSTR) -> c_int;
+        let lstrcmpi_w = resolve("kernel32.dll", FunctionId::name("lstrcmpiW")).unwrap();
+        let detour = unsafe { LstrcmpiW::from_ptr(lstrcmpi_w) };
+        let result = unsafe { Hook::<LstrcmpiW>::create_api("kernel32.dll", FunctionId::name("lstrcmpiW"), detour) };
+        assert_eq!(result.unwrap_err(), Error::TargetIsDetour);
+    }
+
+    #[cfg(target_arch = "x86")]
+    #[test]
+    fn equivalent_abi() {
+        extern "system" fn f(x: i32) -> i32 { x * 223 }
+        extern "stdcall" fn d(x: i32) -> i32 { x * 227 }
+        extern "stdcall" fn g(x: i32) -> i32 { x * 229 }
+        extern "system" fn e(x: i32) -> i32 { x * 233 }
+
+        unsafe {
+            let hf = Hook::<extern "system" fn(i32) -> i32>::create_equivalent_abi(f, d as extern "stdcall" fn(i32) -> i32).unwrap();
+            let hg = Hook::<extern "stdcall" fn(i32) -> i32>::create_equivalent_abi(g, e as extern "system" fn(i32) -> i32).unwrap();
+            hf.enable().unwrap();
+            hg.enable().unwrap();
+            assert_eq!((f(1), g(1)), (227, 233));
+            assert_eq!((hf.call_real(1), hg.call_real(1)), (223, 229));
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn equivalent_abi() {
+        extern "system" fn f(x: i32) -> i32 { x * 223 }
+        extern "C" fn d(x: i32) -> i32 { x * 227 }
+        extern "C" fn g(x: i32) -> i32 { x * 229 }
+        extern "system" fn e(x: i32) -> i32 { x * 233 }
+
+        unsafe {
+            let hf = Hook::<extern "system" fn(i32) -> i32>::create_equivalent_abi(f, d as extern "C" fn(i32) -> i32).unwrap();
+            let hg = Hook::<extern "C" fn(i32) -> i32>::create_equivalent_abi(g, e as extern "system" fn(i32) -> i32).unwrap();
+            hf.enable().unwrap();
+            hg.enable().unwrap();
+            assert_eq!((f(1), g(1)), (227, 233));
+            assert_eq!((hf.call_real(1), hg.call_real(1)), (223, 229));
+        }
+    }
+
+    #[test]
+    fn variadic_hook() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static FIXED: AtomicUsize = AtomicUsize::new(0);
+        extern "C" fn d(x: i32) -> i32 {
+            FIXED.store(x as usize, Ordering::SeqCst);
+            x * 389
+        }
+
+        // Returns the sum of the fixed argument and the first variadic argument.
+        #[cfg(target_arch = "x86_64")]
+        let code = [0x89, 0xc8, 0x01, 0xd0, 0x90, 0x90, 0xc3]; // mov eax, ecx; add eax, edx; nop; nop; ret
+        #[cfg(target_arch = "x86")]
+        let code = [0x8b, 0x44, 0x24, 0x04, 0x03, 0x44, 0x24, 0x08, 0xc3]; // mov eax, [esp+4]; add eax, [esp+8]; ret
+
+        unsafe {
+            let memory = kernel32::VirtualAlloc(ptr::null_mut(), 4096, winapi::MEM_COMMIT | winapi::MEM_RESERVE,
+                                                winapi::PAGE_EXECUTE_READWRITE) as *mut u8;
+            assert!(!memory.is_null());
+            ptr::copy_nonoverlapping(code.as_ptr(), memory, code.len());
+            let f = mem::transmute::<_, extern "C" fn(i32, ...) -> i32>(memory);
+            assert_eq!(f(1, 2i32), 3);
+
+            let h = VariadicHook::<extern "C" fn(i32) -> i32>::create(FnPointer::from_raw(memory as *mut _), d).unwrap();
+            h.enable().unwrap();
+            assert_eq!(f(2, 3i32), 778);
+            assert_eq!(FIXED.load(Ordering::SeqCst), 2);
+
+            let real = mem::transmute::<_, extern "C" fn(i32, ...) -> i32>(h.raw_trampoline().to_raw());
+            assert_eq!(real(2, 3i32), 5);
+
+            mem::drop(h);
+            assert_eq!(f(1, 2i32), 3);
+            kernel32::VirtualFree(memory as winapi::LPVOID, 0, winapi::MEM_RELEASE);
+        }
+    }
+
+    #[test]
+    fn create_from_fn_items() {
+        fn f(x: i32) -> i32 { x * 419 }
+        fn d(x: i32) -> i32 { x * 421 }
+
+        unsafe {
+            let h: Hook<fn(i32) -> i32> = Hook::create(f, d).unwrap();
+            h.enable().unwrap();
+            assert_eq!(f(1), 421);
+            assert!(all_hooks().contains(&FnPointer::of::<fn(i32) -> i32>(f)));
+        }
+    }
+
+    #[test]
+    fn original_shared() {
+        use std::sync::Arc;
+        use std::thread;
+
+        fn f(x: i32) -> i32 { x * 457 }
+        fn d(x: i32) -> i32 { -x }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        h.enable().unwrap();
+        let original = h.original().unwrap();
+        mem::drop(h);
+
+        // The hook stays alive and enabled while the original function is shared.
+        assert_eq!(f(1), -1);
+        let threads = (0..4).map(|i| {
+            let original = original.clone();
+            thread::spawn(move || (*original)(i))
+        }).collect::<Vec<_>>();
+        let results = threads.into_iter().map(|thread| thread.join().unwrap()).collect::<Vec<_>>();
+        assert_eq!(results, vec![0, 457, 914, 1371]);
+
+        assert_eq!(Arc::strong_count(&original), 1);
+        mem::drop(original);
+        assert_eq!(f(1), 457);
+
+        fn g(x: i32) -> i32 { x * 461 }
+
+        static_hooks! {
+            impl sh for g: fn(i32) -> i32 = |x| x;
+        }
+
+        unsafe { sh.initialize().unwrap(); }
+        let old = sh.try_hook().unwrap();
+        assert!(old.original().is_ok());
+        unsafe { sh.destroy().unwrap(); }
+        let error = old.original().unwrap_err();
+        assert_eq!(error, HookError::new(Error::NotCreated, Some(FnPointer::of(g as fn(i32) -> i32)), "share original function"));
+    }
+
+    #[test]
+    fn this_hook_virtual() {
+        use function::MethodArgs;
+
+        type GetValue = <(i32,) as MethodArgs<Object, i32>>::Method;
+
+        #[repr(C)]
+        struct VTable {
+            get_value: GetValue
+        }
+
+        #[repr(C)]
+        struct Object {
+            vtable: *const VTable,
+            value: i32
+        }
+
+        // Defines a method with the calling convention of the target architecture.
+        macro_rules! method {
+            (fn $name:ident($this:ident, $x:ident) -> i32 $body:block) => {
+                #[cfg(target_arch = "x86")]
+                extern "thiscall" fn $name($this: *mut Object, $x: i32) -> i32 $body
+                #[cfg(target_arch = "x86_64")]
+                extern "C" fn $name($this: *mut Object, $x: i32) -> i32 $body
+            };
+        }
+
+        method!(fn get_value(this, x) -> i32 { unsafe { (*this).value + x } });
+        method!(fn detour(this, x) -> i32 { unsafe { (*this).value * 463 + x } });
+
+        static VTABLE: VTable = VTable { get_value: get_value };
+        let mut object = Object { vtable: &VTABLE, value: 5 };
+
+        // The method is loaded through a volatile read, so the call can not be devirtualized.
+        let call = |object: &mut Object, x| unsafe {
+            ptr::read_volatile(&(*object.vtable).get_value)(object, x)
+        };
+        assert_eq!(call(&mut object, 1), 6);
+
+        unsafe {
+            let h = ThisHook::<Object, (i32,), i32>::create_virtual(&object, 0, detour as GetValue).unwrap();
+            h.enable().unwrap();
+            assert_eq!(call(&mut object, 1), 2316);
+            object.value = 7;
+            assert_eq!(call(&mut object, 1), 3242);
+            assert_eq!(h.call_original(&mut object, (1,)), 8);
+        }
+    }
+
+    #[test]
+    fn create_closure() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        fn f(x: i32) -> i32 { x * 443 }
+        fn g(x: i32) -> i32 { x * 449 }
+        fn h(x: i32) -> i32 { x * 521 }
+
+        fn hook(target: fn(i32) -> i32, calls: Arc<AtomicUsize>) -> HookResult<ClosureHook<fn(i32) -> i32>> {
+            unsafe { Hook::create_closure(target, move |x| x + calls.fetch_add(1, Ordering::SeqCst) as i32) }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let factor = 2;
+        let hf = unsafe { Hook::<fn(i32) -> i32>::create_closure(f, move |x| x * factor).unwrap() };
+        let hg = hook(g, calls.clone()).unwrap();
+        hf.enable().unwrap();
+        hg.enable().unwrap();
+        assert_eq!(f(1), 2);
+        assert_eq!((g(10), g(10)), (10, 11));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!((hf.call_real(1), hg.call_real(1)), (443, 449));
+
+        // The closure type of `hook()` is the same for every call.
+        let hh = hook(h, calls.clone()).unwrap();
+        hh.enable().unwrap();
+        assert_eq!((g(10), h(10)), (12, 13));
+        mem::drop(hg);
+        assert_eq!((g(1), h(1)), (449, 5));
+        mem::drop(hh);
+        assert_eq!(h(1), 521);
+        assert_eq!(Arc::strong_count(&calls), 1);
+    }
+
+    #[test]
+    fn create_with_relay() {
+        fn f(x: i32) -> i32 { x * 397 }
+        fn g(x: i32) -> i32 { x * 401 }
+        fn d(x: i32) -> i32 { x * 409 }
+
+        unsafe {
+            let hf = Hook::<fn(i32) -> i32>::create_with_relay(f, d, None).unwrap();
+            let hg = Hook::<fn(i32) -> i32>::create_with_relay(g, d, Some(ptr::null_mut())).unwrap();
+            hf.enable().unwrap();
+            hg.enable().unwrap();
+            assert_eq!((f(1), g(1)), (409, 409));
+            assert!(hf.verify_intact());
+            mem::drop((hf, hg));
+
+            // The relay does not have to be writable.
+            let relay = kernel32::VirtualAlloc(ptr::null_mut(), 4096, winapi::MEM_COMMIT | winapi::MEM_RESERVE,
+                                               winapi::PAGE_EXECUTE_READ);
+            assert!(!relay.is_null());
+            let h = Hook::<fn(i32) -> i32>::create_with_relay(f, d, Some(relay)).unwrap();
+            h.enable().unwrap();
+            assert_eq!(f(1), 409);
+            assert_eq!(h.call_real(1), 397);
+            assert!(h.verify_intact());
+            // The hook is registered with the detour function, not with the relay.
+            let same = Hook::<fn(i32) -> i32>::create_or_get(f, d).unwrap();
+            assert_eq!(same, h);
+            mem::drop((h, same));
+
+            // The relay is written before the hook is created, and restored if that fails.
+            let error = Hook::<fn(i32) -> i32>::create_with_relay(d, d, Some(relay)).unwrap_err();
+            assert_eq!(error, Error::TargetIsDetour);
+            assert!(slice::from_raw_parts(relay as *const u8, 16).iter().all(|&byte| byte == 0));
+            kernel32::VirtualFree(relay, 0, winapi::MEM_RELEASE);
+        }
+    }
+
+    #[test]
+    fn create_at_mid_function() {
+        unsafe fn alloc_stub(code: &[u8]) -> *mut u8 {
+            let memory = kernel32::VirtualAlloc(ptr::null_mut(), 4096, winapi::MEM_COMMIT | winapi::MEM_RESERVE,
+                                                winapi::PAGE_EXECUTE_READWRITE) as *mut u8;
+            assert!(!memory.is_null());
+            ptr::copy_nonoverlapping(code.as_ptr(), memory, code.len());
+            memory
+        }
+
+        extern "system" fn d() -> u32 { 337 }
+
+        unsafe {
+            // nop; mov eax, 42; nop; nop; nop; ret
+            let stub = alloc_stub(&[0x90, 0xb8, 0x2a, 0x00, 0x00, 0x00, 0x90, 0x90, 0x90, 0xc3]);
+            let f = mem::transmute::<_, extern "system" fn() -> u32>(stub);
+            let target = FnPointer::from_raw(stub as *mut _).offset(1);
+
+            let h = Hook::<extern "system" fn() -> u32>::create_at(target, d).unwrap();
+            assert_eq!(f(), 42);
+            h.enable().unwrap();
+            assert_eq!(f(), 337);
+            assert_eq!(h.call_real(), 42);
+            mem::drop(h);
+            assert_eq!(f(), 42);
+
+            // nop; ret; mov eax, 42; ret
+            let stub2 = alloc_stub(&[0x90, 0xc3, 0xb8, 0x2a, 0x00, 0x00, 0x00, 0xc3]);
+            let target = FnPointer::from_raw(stub2 as *mut _).offset(1);
+            assert_eq!(Hook::<extern "system" fn() -> u32>::create_at(target, d).unwrap_err(), Error::UnsupportedFunction);
+
+            kernel32::VirtualFree(stub as winapi::LPVOID, 0, winapi::MEM_RELEASE);
+            kernel32::VirtualFree(stub2 as winapi::LPVOID, 0, winapi::MEM_RELEASE);
+        }
+    }
+
+    #[test]
+    fn can_hook_tiny_function() {
+        unsafe {
+            let memory = kernel32::VirtualAlloc(ptr::null_mut(), 4096, winapi::MEM_COMMIT | winapi::MEM_RESERVE,
+                                                winapi::PAGE_EXECUTE_READWRITE) as *mut u8;
+            assert!(!memory.is_null());
+
+            // ret; mov eax, 42; ret
+            let tiny = [0xc3, 0xb8, 0x2a, 0x00, 0x00, 0x00, 0xc3];
+            ptr::copy_nonoverlapping(tiny.as_ptr(), memory, tiny.len());
+            let target = FnPointer::from_raw(memory as *mut _);
+            let error = can_hook(target).unwrap_err();
+            assert_eq!(error, Error::FunctionTooSmall { size: 1 });
+            assert!(error.is_target_unhookable());
+            assert_eq!(error.to_string(), "The specified target function ends after 1 bytes, which leaves no room for \
+                                           the 5 byte jump");
+
+            // mov eax, 42; ret
+            ptr::copy_nonoverlapping(tiny[1..].as_ptr(), memory, tiny.len() - 1);
+            assert_eq!(can_hook(target), Ok(()));
+
+            kernel32::VirtualFree(memory as winapi::LPVOID, 0, winapi::MEM_RELEASE);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn hook_spec_json() {
+        use serde_json;
+
+        let spec = HookSpec {
+            module: "kernel32.dll".to_string(),
+            function: "lstrcmpA".parse().unwrap(),
+            enabled: true
+        };
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(json, r#"{"module":"kernel32.dll","function":"lstrcmpA","enabled":true}"#);
+        assert_eq!(serde_json::from_str::<HookSpec>(&json).unwrap(), spec);
+
+        let ordinal = serde_json::to_string(&FunctionId::ordinal(42)).unwrap();
+        assert_eq!(ordinal, r##""#42""##);
+        assert_eq!(serde_json::from_str::<OwnedFunctionId>(&ordinal).unwrap(), OwnedFunctionId::Ordinal(42));
+        assert!(serde_json::from_str::<OwnedFunctionId>("\"\"").is_err());
+
+        type LstrcmpA = extern "system" fn(winapi::LPCSTR, winapi::LPCSTR) -> c_int;
+        extern "system" fn d(_: winapi::LPCSTR, _: winapi::LPCSTR) -> c_int { 373 }
+
+        unsafe {
+            let hook = spec.install::<LstrcmpA, _>(d as LstrcmpA).unwrap();
+            let (a, b) = (b"a\0", b"b\0");
+            assert_eq!(kernel32::lstrcmpA(a.as_ptr() as winapi::LPCSTR, b.as_ptr() as winapi::LPCSTR), 373);
+            mem::drop(hook);
+            assert!(kernel32::lstrcmpA(a.as_ptr() as winapi::LPCSTR, b.as_ptr() as winapi::LPCSTR) < 0);
+        }
+    }
+
+    #[test]
+    fn fn_pointer_default_null() {
+        fn f(x: i32) -> i32 { x * 331 }
+
+        assert!(FnPointer::default().is_null());
+        assert_eq!(FnPointer::default().addr(), 0);
+        assert!(!FnPointer::of(f as fn(i32) -> i32).is_null());
+
+        let result = unsafe { Hook::<fn(i32) -> i32>::create_with_ptr_detour(f, FnPointer::default()) };
+        assert_eq!(result.unwrap_err(), Error::NotExecutable);
+        let result = unsafe { Hook::<fn(i32) -> i32>::create_at(FnPointer::default(), f) };
+        assert_eq!(result.unwrap_err(), Error::NotExecutable);
+    }
+
+    #[test]
+    fn create_ptr_detour() {
+        fn f(x: i32) -> i32 { x * 149 }
+        fn d(x: i32) -> i32 { x * 151 }
+
+        let detour = FnPointer::of(d as fn(i32) -> i32);
+        let h = unsafe { Hook::<fn(i32) -> i32>::create_with_ptr_detour(f, detour).unwrap() };
+        h.enable().unwrap();
+        assert_eq!(f(1), 151);
+        assert_eq!(unsafe { h.trampoline()(1) }, 149);
+        h.disable().unwrap();
+        assert_eq!(f(1), 149);
+    }
+
+    #[test]
+    fn queue_append_extend() {
+        fn f(x: i32) -> i32 { x * 113 }
+        fn g(x: i32) -> i32 { x * 127 }
+        fn d(x: i32) -> i32 { -x }
+
+        let hf = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        let hg = unsafe { Hook::<fn(i32) -> i32>::create(g, d).unwrap() };
+
+        let mut first = HookQueue::new();
+        first.enable(&hf);
+        let mut second = HookQueue::new();
+        second.enable(&hg);
+
+        first.append(second).apply().unwrap();
+        assert_eq!((f(1), g(1)), (-1, -1));
+
+        let mut queue = HookQueue::new();
+        queue.extend(vec![(FnPointer::of(f as fn(i32) -> i32), false), (FnPointer::of(g as fn(i32) -> i32), false)]);
+        queue.apply().unwrap();
+        assert_eq!((f(1), g(1)), (113, 127));
+
+        fn unhooked(x: i32) -> i32 { x * 131 }
+        let mut queue = HookQueue::new();
+        queue.enable(&hf).extend(Some((FnPointer::of(unhooked as fn(i32) -> i32), true)));
+        assert_eq!(queue.apply().unwrap_err(), Error::NotCreated);
+        assert_eq!(f(1), 113);
+    }
+
+    #[test]
+    fn queue_ptr() {
+        use api::RealApi;
+
+        fn f(x: i32) -> i32 { x * 347 }
+        fn g(x: i32) -> i32 { x * 349 }
+        fn d(x: i32) -> i32 { -x }
+
+        let hf = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        let hg = unsafe { Hook::<fn(i32) -> i32>::create(g, d).unwrap() };
+        let targets = [FnPointer::of(f as fn(i32) -> i32), FnPointer::of(g as fn(i32) -> i32)];
+
+        let mut queue = HookQueue::new();
+        queue.enable_ptr(targets[0]).enable_ptr(targets[1]);
+        queue.apply().unwrap();
+        assert_eq!((f(1), g(1)), (-1, -1));
+
+        let mut queue = HookQueue::new();
+        queue.disable_ptr(targets[1]);
+        queue.apply().unwrap();
+        assert_eq!((f(1), g(1)), (-1, 349));
+
+        mem::drop((hf, hg));
+        let mut queue = HookQueue::new();
+        queue.enable_ptr(targets[0]);
+        let error = HookError::new(Error::NotCreated, Some(targets[0]), "queue hook change");
+        assert_eq!(queue.apply(), Err(error));
+        // Without the check for dropped hooks, as if the hook was dropped by another thread in
+        // the meantime.
+        assert_eq!(queue.apply_with(&RealApi), Err(error));
+        assert_eq!(f(1), 347);
+    }
+
+    #[test]
+    fn disable_module_hooks() {
+        fn f(x: i32) -> i32 { x * 379 }
+        fn d(x: i32) -> i32 { -x }
+
+        type GetSystemMetrics = extern "system" fn(c_int) -> c_int;
+        extern "system" fn e(_: c_int) -> c_int { -383 }
+
+        // No other test hooks functions in this module.
+        let _user32 = Module::load("user32.dll").unwrap();
+        let get_system_metrics = resolve("user32.dll", FunctionId::name("GetSystemMetrics")).unwrap();
+        let get_system_metrics = unsafe { GetSystemMetrics::from_ptr(get_system_metrics) };
+
+        unsafe {
+            let hf = Hook::<fn(i32) -> i32>::create(f, d).unwrap();
+            let he = Hook::<GetSystemMetrics>::create_api("user32.dll", FunctionId::name("GetSystemMetrics"), e as GetSystemMetrics).unwrap();
+            hf.enable().unwrap();
+            he.enable().unwrap();
+            assert_eq!((f(1), get_system_metrics(0)), (-1, -383));
+
+            assert_eq!(disable_module("user32.dll").unwrap(), 1);
+            assert_eq!(f(1), -1);
+            assert!(get_system_metrics(0) >= 0);
+            assert_eq!(disable_module("user32.dll").unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn enabled_hook_counter() {
+        use std::sync::PoisonError;
+        use super::REGISTRY;
+
+        fn f(x: i32) -> i32 { x * 431 }
+        fn g(x: i32) -> i32 { x * 433 }
+        fn d(x: i32) -> i32 { -x }
+
+        // Other tests enable hooks at the same time, so compare the counter with the registry
+        // while it is locked instead of comparing it with a fixed number.
+        fn check(f: fn(i32) -> i32, enabled: bool) {
+            let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+            let target = FnPointer::of(f);
+            assert_eq!(registry.iter().find(|entry| entry.target == target).map(|entry| entry.enabled), Some(enabled));
+            assert_eq!(enabled_hook_count(), registry.iter().filter(|entry| entry.enabled).count());
+        }
+
+        unsafe {
+            let hf = Hook::<fn(i32) -> i32>::create(f, d).unwrap();
+            let hg = Hook::<fn(i32) -> i32>::create(g, d).unwrap();
+            check(f, false);
+
+            hf.enable().unwrap();
+            check(f, true);
+            assert!(enabled_hook_count() >= 1);
+            assert!(hf.enable().is_err());
+            check(f, true);
+
+            let mut queue = HookQueue::new();
+            queue.disable(&hf).enable(&hg);
+            queue.apply().unwrap();
+            check(f, false);
+            check(g, true);
+
+            let target = FnPointer::of(g as fn(i32) -> i32);
+            mem::drop((hf, hg));
+            let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+            assert!(registry.iter().all(|entry| entry.target != target));
+            assert_eq!(enabled_hook_count(), registry.iter().filter(|entry| entry.enabled).count());
+        }
+    }
+
+    #[test]
+    fn hook_mock_api() {
+        use api::MockApi;
+
+        fn f(x: i32) -> i32 { x * 487 }
+        fn d(x: i32) -> i32 { x * 491 }
+
+        let api = MockApi::default();
+        let (target, detour) = (FnPointer::of(f as fn(i32) -> i32), FnPointer::of(d as fn(i32) -> i32));
+        let h = unsafe { Hook::<fn(i32) -> i32>::create_at_with(&api, target, detour).unwrap() };
+        assert!(all_hooks().contains(&target));
+        h.enable_with(&api).unwrap();
+        h.disable_with(&api).unwrap();
+        assert_eq!(h.remove_with(&api).unwrap(), target);
+        assert!(!all_hooks().contains(&target));
+        assert_eq!(api.calls(), vec![
+            ("create", Some(target)),
+            ("enable", Some(target)),
+            ("disable", Some(target)),
+            ("remove", Some(target))
+        ]);
+
+        // Nothing was patched.
+        assert_eq!(f(1), 487);
+
+        let api = MockApi::default();
+        let error = unsafe {
+            Hook::<fn(i32) -> i32>::create_api_with(&api, "kernel32.dll", FunctionId::name("lstrlenA"), detour).unwrap_err()
+        };
+        assert_eq!(error, HookError::new(Error::FunctionNotFound, None, "create hook for exported function"));
+        assert_eq!(api.calls(), vec![("create_api", None)]);
+    }
+
+    #[test]
+    fn queue_report() {
+        use api::MockApi;
+
+        fn f(x: i32) -> i32 { x * 499 }
+        fn g(x: i32) -> i32 { x * 503 }
+        fn h(x: i32) -> i32 { x * 509 }
+        fn d(x: i32) -> i32 { -x }
+
+        // The hooks are only registered, nothing is patched.
+        let api = MockApi::default();
+        let create = |target: fn(i32) -> i32| unsafe {
+            Hook::<fn(i32) -> i32>::create_at_with(&api, FnPointer::of(target), FnPointer::of(d as fn(i32) -> i32)).unwrap()
+        };
+        let (hf, hg, hh) = (create(f), create(g), create(h));
+        hh.enable_with(&api).unwrap();
+
+        let mut queue = HookQueue::new();
+        queue.enable(&hf).enable(&hg).disable(&hh);
+        assert_eq!(queue.apply_with(&api).unwrap(), ApplyReport { enabled: 2, disabled: 1 });
+
+        // Only hooks that change state are counted.
+        assert_eq!(queue.apply_with(&api).unwrap(), ApplyReport::default());
+        let mut queue = HookQueue::new();
+        queue.enable(&hf).enable(&hh);
+        assert_eq!(queue.apply_with(&api).unwrap(), ApplyReport { enabled: 1, disabled: 0 });
+
+        hf.remove_with(&api).unwrap();
+        hg.remove_with(&api).unwrap();
+        hh.remove_with(&api).unwrap();
+    }
+
+    #[test]
+    fn queue_priority() {
+        use api::MockApi;
+
+        let (a, b, c, d) = unsafe {
+            (FnPointer::from_addr(0x1000), FnPointer::from_addr(0x2000), FnPointer::from_addr(0x3000), FnPointer::from_addr(0x4000))
+        };
+        let mut queue = HookQueue(vec![(a, true, 0), (b, true, 10), (c, false, 5), (d, true, 10), (a, true, -1)]);
+
+        let api = MockApi::default();
+        queue.apply_with(&api).unwrap();
+        assert_eq!(api.calls(), vec![
+            ("queue_enable", Some(b)),
+            ("queue_enable", Some(d)),
+            ("queue_disable", Some(c)),
+            ("queue_enable", Some(a)),
+            ("apply_queued", None)
+        ]);
+    }
+
+    #[cfg(feature = "lazy_static")]
+    #[test]
+    fn queue_poisoned_lock() {
+        use std::thread;
+        use super::QUEUE_LOCK;
+
+        let _ = thread::spawn(|| {
+            let _lock = QUEUE_LOCK.lock().unwrap();
+            panic!("poison the lock");
+        }).join();
+        assert!(QUEUE_LOCK.is_poisoned());
+
+        fn f(x: i32) -> i32 { x * 13 }
+        fn d(x: i32) -> i32 { x * 17 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        HookQueue::new().enable(&h).apply().unwrap();
+        assert_eq!(f(1), 17);
+        HookQueue::new().disable(&h).apply().unwrap();
+        assert_eq!(f(1), 13);
+    }
+
+    #[test]
+    fn forwarded_export() {
+        type VerSetConditionMask = extern "system" fn(winapi::ULONGLONG, winapi::DWORD, winapi::BYTE) -> winapi::ULONGLONG;
+
+        extern "system" fn ver_set_condition_mask_detour(_mask: winapi::ULONGLONG, _type_mask: winapi::DWORD, _condition: winapi::BYTE) -> winapi::ULONGLONG {
+            42
+        }
+
+        // `kernel32!VerSetConditionMask` is forwarded to `ntdll!VerSetConditionMask`.
+        let target = resolve_forwarded("kernel32.dll", FunctionId::name("VerSetConditionMask")).unwrap();
+        assert_eq!(target, resolve("ntdll.dll", FunctionId::name("VerSetConditionMask")).unwrap());
+
+        unsafe {
+            let ver_set_condition_mask: VerSetConditionMask = mem::transmute(resolve("kernel32.dll", FunctionId::name("VerSetConditionMask")).unwrap().to_raw());
+
+            let h = Hook::<VerSetConditionMask>::create_api_resolved(
+                "kernel32.dll",
+                FunctionId::name("VerSetConditionMask"),
+                ver_set_condition_mask_detour).unwrap();
+            let expected = ver_set_condition_mask(0, 1, 1);
+            assert!(expected != 42);
+            h.enable().unwrap();
+            assert_eq!(ver_set_condition_mask(0, 1, 1), 42);
+            assert_eq!(h.call_real(0, 1, 1), expected);
+        }
+
+        assert_eq!(resolve_forwarded("kernel32.dll", FunctionId::name("NoSuchFunction")).unwrap_err(), Error::FunctionNotFound);
+    }
+
+    #[test]
+    fn function_id_parse() {
+        assert_eq!(FunctionId::parse("#42"), Ok(FunctionId::Ordinal(42)));
+        assert_eq!(FunctionId::parse("#65535"), Ok(FunctionId::Ordinal(65535)));
+        assert_eq!(FunctionId::parse("Foo"), Ok(FunctionId::Name(OsStr::new("Foo"))));
+        assert_eq!(FunctionId::parse("#"), Ok(FunctionId::Name(OsStr::new("#"))));
+        assert_eq!(FunctionId::parse("#+1"), Ok(FunctionId::Name(OsStr::new("#+1"))));
+        assert_eq!(FunctionId::parse("#65536"), Err(Error::InvalidFunctionName));
+        assert_eq!(FunctionId::parse("#99999"), Err(Error::InvalidFunctionName));
+        assert_eq!("#99999".parse::<OwnedFunctionId>(), Err(Error::InvalidFunctionName));
+    }
+
+    #[test]
+    fn unsafe_extern_target_and_detour() {
+        unsafe extern "system" fn f(x: i32) -> i32 { x * 311 }
+        unsafe extern "system" fn d(x: i32) -> i32 { x * 313 }
+
+        unsafe {
+            let h = Hook::<unsafe extern "system" fn(i32) -> i32>::create(f, d).unwrap();
+            h.enable().unwrap();
+            assert_eq!(f(1), 313);
+            assert_eq!(h.call_real(1), 311);
+        }
+
+        unsafe extern "system" fn g(x: i32) -> i32 { x * 317 }
+
+        // Unsafe functions do not implement the `Fn` traits, so the detour is a closure.
+        static_hooks! {
+            impl hg for g: unsafe extern "system" fn(i32) -> i32 = |x| unsafe { hg.call_real(x) + 1 };
+        }
+
+        unsafe {
+            hg.initialize().unwrap();
+            hg.enable().unwrap();
+            assert_eq!(g(1), 318);
+            assert_eq!(hg.call_real(1), 317);
+        }
+    }
+
+    #[test]
+    fn create_or_get() {
+        fn f(x: i32) -> i32 { x * 283 }
+        fn d(x: i32) -> i32 { x * 293 }
+        fn e(x: i32) -> i32 { x * 307 }
+
+        let h1 = unsafe { Hook::<fn(i32) -> i32>::create_or_get(f, d).unwrap() };
+        let h2 = unsafe { Hook::<fn(i32) -> i32>::create_or_get(f, d).unwrap() };
+        assert!(h1 == h2);
+        assert_eq!(unsafe { Hook::<fn(i32) -> i32>::create_or_get(f, e) }.unwrap_err(), Error::AlreadyCreated);
+
+        h2.enable().unwrap();
+        assert_eq!(f(1), 293);
+        assert_eq!(unsafe { h1.trampoline()(1) }, 283);
+
+        // Only the last handle removes the hook.
+        mem::drop(h1);
+        assert_eq!(f(1), 293);
+        assert!(all_hooks().contains(&FnPointer::of(f as fn(i32) -> i32)));
+
+        h2.remove().unwrap();
+        assert_eq!(f(1), 283);
+        assert!(!all_hooks().contains(&FnPointer::of(f as fn(i32) -> i32)));
+    }
+
+    #[test]
+    fn hook_targets() {
+        fn f(x: i32) -> i32 { x * 271 }
+        fn g(x: i32) -> i32 { x * 277 }
+        fn d(x: i32) -> i32 { x * 281 }
+
+        let h1 = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        assert!(h1.targets(&(f as fn(i32) -> i32)));
+        assert!(!h1.targets(&(g as fn(i32) -> i32)));
+        assert!(!h1.targets(&(d as fn(i32) -> i32)));
+
+        let h2 = unsafe { Hook::<fn(i32) -> i32>::create(g, d).unwrap() };
+        assert!(h1 != h2);
+
+        let hooks = vec![h1];
+        assert!(hooks.contains(&hooks[0]));
+        assert!(!hooks.contains(&h2));
+    }
+
+    #[test]
+    fn hook_error_context() {
+        extern "system" fn d(_: winapi::LPCWSTR) -> c_int { 0 }
+
+        let error = unsafe {
+            Hook::<extern "system" fn(winapi::LPCWSTR) -> c_int>::create_api("kernel32.dll", FunctionId::name("DoesNotExist"), d)
+        }.unwrap_err();
+        assert_eq!(error.source, Error::FunctionNotFound);
+        assert_eq!(error.target, None);
+        assert_eq!(error.to_string(), format!("Failed to create hook for exported function: {}", Error::FunctionNotFound));
+
+        fn f(x: i32) -> i32 { x * 263 }
+        fn g(x: i32) -> i32 { x * 269 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, g).unwrap() };
+        let error = h.disable().unwrap_err();
+        assert_eq!(error, HookError::new(Error::Disabled, Some(FnPointer::of(f as fn(i32) -> i32)), "disable hook"));
+        assert!(error.to_string().starts_with("Failed to disable hook for 0x"));
+    }
+
+    #[test]
+    fn create_not_executable() {
+        fn f(x: u32) -> u32 { x * 109 }
+
+        static DATA: u32 = 0;
+
+        let result = unsafe {
+            Hook::<fn(u32) -> u32>::create_at(FnPointer::from_raw(&DATA as *const _ as *mut _), f)
+        };
+        let error = result.unwrap_err();
+        assert_eq!(error, Error::NotExecutableDetail { protect: winapi::PAGE_READONLY });
+        assert!(error.source.is_target_unhookable());
+        assert!(error.to_string().contains("0x2"));
+    }
+
+    #[test]
+    fn function_id_display_from_str() {
+        let name = "lstrlenW".parse::<OwnedFunctionId>().unwrap();
+        assert_eq!(name, OwnedFunctionId::Name("lstrlenW".to_string()));
+        assert_eq!(name.to_string(), "lstrlenW");
+
+        // `FunctionId` is `Copy`, so the same identifier can be passed by value more than once.
+        let id = name.as_function_id();
+        assert_eq!(resolve("kernel32.dll", id).unwrap(), resolve("kernel32.dll", FunctionId::name("lstrlenW")).unwrap());
+        assert!(resolve("kernel32.dll", id).is_ok());
+
+        let ordinal = "#7".parse::<OwnedFunctionId>().unwrap();
+        assert_eq!(ordinal, OwnedFunctionId::Ordinal(7));
+        assert_eq!(ordinal.to_string(), "#7");
+        assert_eq!(ordinal.as_function_id(), FunctionId::Ordinal(7));
+
+        assert_eq!(FunctionId::name("lstrlenW").to_string(), "lstrlenW");
+        assert_eq!("".parse::<OwnedFunctionId>(), Err(Error::InvalidFunctionName));
+    }
+
+    #[test]
+    fn create_retry() {
+        use std::time::Duration;
+        use api::MockApi;
+
+        fn f(x: i32) -> i32 { x * 37 }
+        fn d(x: i32) -> i32 { x * 41 }
+
+        let (target, detour) = (FnPointer::of(f as fn(i32) -> i32), FnPointer::of(d as fn(i32) -> i32));
+        let creates = |api: &MockApi| api.calls().iter().filter(|&&(name, _)| name == "create").count();
+        let create = |api: &MockApi, attempts| unsafe {
+            Hook::<fn(i32) -> i32>::create_with_retry_with(api, target, detour, attempts, Duration::from_millis(1))
+        };
+
+        // Transient errors are retried.
+        let api = MockApi {
+            fail_create: Mutex::new(vec![Error::UnsupportedFunction, Error::MemoryProtect]),
+            ..MockApi::default()
+        };
+        let h = create(&api, 3).unwrap();
+        assert_eq!(creates(&api), 3);
+        assert_eq!(h.remove_with(&api).unwrap(), target);
+
+        // The last error is returned once all attempts have failed.
+        let api = MockApi {
+            fail_create: Mutex::new(vec![Error::MemoryProtect, Error::MemoryProtect, Error::MemoryProtect]),
+            ..MockApi::default()
+        };
+        assert_eq!(create(&api, 3).unwrap_err(), HookError::new(Error::MemoryProtect, Some(target), "create hook"));
+        assert_eq!(creates(&api), 3);
+
+        // Other errors are not retried.
+        let api = MockApi {
+            fail_create: Mutex::new(vec![Error::MemoryAlloc, Error::MemoryAlloc]),
+            ..MockApi::default()
+        };
+        assert_eq!(create(&api, 3).unwrap_err(), HookError::new(Error::MemoryAlloc, Some(target), "create hook"));
+        assert_eq!(creates(&api), 1);
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create_with_retry(f, d, 3, Duration::from_millis(1)).unwrap() };
+        h.enable().unwrap();
+        assert_eq!(f(1), 41);
+    }
+
+    #[test]
+    fn registry() {
+        fn f1(x: i32) -> i32 { x * 43 }
+        fn d1(x: i32) -> i32 { x * 47 }
+        fn f2(x: i32) -> i32 { x * 53 }
+        fn d2(x: i32) -> i32 { x * 59 }
+
+        let (h1, h2) = unsafe {
+            (Hook::<fn(i32) -> i32>::create(f1, d1).unwrap(),
+             Hook::<fn(i32) -> i32>::create(f2, d2).unwrap())
+        };
+        let (t1, t2) = ((f1 as fn(i32) -> i32).to_ptr(), (f2 as fn(i32) -> i32).to_ptr());
+
+        let hooks = all_hooks();
+        assert!(hooks.contains(&t1));
+        assert!(hooks.contains(&t2));
+
+        mem::drop(h1);
+        let hooks = all_hooks();
+        assert!(!hooks.contains(&t1));
+        assert!(hooks.contains(&t2));
+
+        h2.remove().unwrap();
+        assert!(!all_hooks().contains(&t2));
+    }
+
+    #[test]
+    fn queue_scoped() {
+        fn f1(x: i32) -> i32 { x * 71 }
+        fn d1(x: i32) -> i32 { x * 73 }
+        fn f2(x: i32) -> i32 { x * 79 }
+        fn d2(x: i32) -> i32 { x * 83 }
+        fn f3(x: i32) -> i32 { x * 89 }
+        fn d3(x: i32) -> i32 { x * 97 }
+
+        let hooks = unsafe {
+            [Hook::<fn(i32) -> i32>::create(f1, d1).unwrap(),
+             Hook::<fn(i32) -> i32>::create(f2, d2).unwrap(),
+             Hook::<fn(i32) -> i32>::create(f3, d3).unwrap()]
+        };
+
+        {
+            let _guard = Hook::apply_queue_scoped(&[&hooks[0], &hooks[1], &hooks[2]]).unwrap();
+            assert_eq!((f1(1), f2(1), f3(1)), (73, 83, 97));
+        }
+
+        assert_eq!((f1(1), f2(1), f3(1)), (71, 79, 89));
+    }
+
+    #[test]
+    fn hookable_function() {
+        hookable! {
+            fn f(x: i32) -> i32 { x * 101 }
+            fn d(x: i32) -> i32 { x * 103 }
+        }
+
+        fn caller() -> i32 { f(1) + f(2) }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        assert_eq!(caller(), 303);
+        h.enable().unwrap();
+        assert_eq!(caller(), 309);
+    }
+
+    #[test]
+    fn hook_debug() {
+        fn f(x: i32) -> i32 { x * 19 }
+        fn d(x: i32) -> i32 { x * 23 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        let output = format!("{:?}", h);
+        assert!(output.contains(&format!("{:p}", (f as fn(i32) -> i32).to_ptr())));
+        assert!(output.contains("arity: 1"));
+        assert!(output.contains("Rust"));
+    }
+
+    #[test]
+    fn local_typestate() {
+        fn f(x: i32) -> i32 { x + 11 }
+        fn d(x: i32) -> i32 { x - 11 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::new_disabled(f, d).unwrap() };
+        assert_eq!(f(1), 12);
+        assert_eq!(h.call_real(1), 12);
+
+        let h = h.enable().unwrap();
+        assert_eq!(f(1), -10);
+        assert_eq!(h.call_real(1), 12);
+        assert_eq!(unsafe { h.trampoline()(1) }, 12);
+
+        let h = h.disable().unwrap();
+        assert_eq!(f(1), 12);
+
+        let h = h.enable().unwrap().into_inner();
+        assert_eq!(f(1), -10);
+        mem::drop(h);
+        assert_eq!(f(1), 12);
+    }
+
+    #[cfg(feature = "capi")]
+    #[test]
+    fn capi() {
+        use capi::{minhook_create, minhook_disable, minhook_enable, minhook_remove};
+        use ffi::MH_STATUS;
+
+        fn f(x: i32) -> i32 { x * 199 }
+        fn d(x: i32) -> i32 { x * 211 }
+
+        let target = f as fn(i32) -> i32 as *mut _;
+        let mut trampoline = ptr::null_mut();
+        unsafe {
+            assert_eq!(minhook_create(target, d as fn(i32) -> i32 as *mut _, &mut trampoline), MH_STATUS::MH_OK as i32);
+            assert_eq!(minhook_enable(target), MH_STATUS::MH_OK as i32);
+            assert_eq!(f(1), 211);
+            assert_eq!(mem::transmute::<_, fn(i32) -> i32>(trampoline)(1), 199);
+            assert_eq!(minhook_disable(target), MH_STATUS::MH_OK as i32);
+            assert_eq!(f(1), 199);
+            assert_eq!(minhook_remove(target), MH_STATUS::MH_OK as i32);
+            assert_eq!(minhook_remove(target), MH_STATUS::MH_ERROR_NOT_CREATED as i32);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        use std::sync::Arc;
+        use metrics::{self, Operation};
+
+        fn f(x: i32) -> i32 { x * 191 }
+        fn d(x: i32) -> i32 { x * 193 }
+
+        let target = FnPointer::of(f as fn(i32) -> i32);
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let callback_records = records.clone();
+        metrics::set_callback(move |operation, callback_target, _duration, result| {
+            if callback_target == Some(target) {
+                callback_records.lock().unwrap().push((operation, result));
+            }
+        });
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        h.enable().unwrap();
+        h.disable().unwrap();
+        assert_eq!(h.disable().unwrap_err(), Error::Disabled);
+        metrics::clear_callback();
+
+        assert_eq!(*records.lock().unwrap(), vec![
+            (Operation::Create, Ok(())),
+            (Operation::Enable, Ok(())),
+            (Operation::Disable, Ok(())),
+            (Operation::Disable, Err(Error::Disabled))
+        ]);
+    }
+
+    #[cfg(all(feature = "logging", feature = "lazy_static"))]
+    #[test]
+    fn logging() {
+        use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord};
+
+        lazy_static! {
+            static ref MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        }
+
+        struct Logger;
+
+        impl Log for Logger {
+            fn enabled(&self, _metadata: &LogMetadata) -> bool { true }
+
+            fn log(&self, record: &LogRecord) {
+                MESSAGES.lock().unwrap().push(format!("{}", record.args()));
+            }
+        }
+
+        fn f(x: i32) -> i32 { x * 4 }
+        fn d(x: i32) -> i32 { x * 5 }
+
+        log::set_logger(|max_level| {
+            max_level.set(LogLevelFilter::Debug);
+            Box::new(Logger)
+        }).unwrap();
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        h.enable().unwrap();
+
+        let target = format!("{:p}", h.target);
+        let messages = MESSAGES.lock().unwrap();
+        assert!(messages.iter().any(|message| message.starts_with("create hook") && message.contains(&target)));
+        assert!(messages.iter().any(|message| message.starts_with("enable hook") && message.contains(&target)));
+    }
+
+    #[test]
+    fn fn_pointer_arithmetic() {
+        fn f() {}
+
+        let ptr = (f as fn()).to_ptr();
+        unsafe {
+            assert_eq!(ptr.offset(8).addr(), ptr.addr() + 8);
+            assert_eq!(ptr.offset(8).offset(-8), ptr);
+            assert_eq!(FnPointer::from_addr(ptr.addr()), ptr);
+        }
+    }
+
+    #[test]
+    fn fn_pointer_ordering() {
+        use std::collections::BTreeSet;
+
+        let pointers = unsafe { [FnPointer::from_addr(0x3000), FnPointer::from_addr(0x1000), FnPointer::from_addr(0x2000)] };
+        let set = pointers.iter().cloned().chain(Some(pointers[0])).collect::<BTreeSet<_>>();
+        assert_eq!(set.into_iter().map(|ptr| ptr.addr()).collect::<Vec<_>>(), vec![0x1000, 0x2000, 0x3000]);
+    }
+
+    #[test]
+    fn fn_pointer_cast() {
+        fn f(x: i32) -> i32 { x * 61 }
+
+        let ptr = FnPointer::of(f as fn(i32) -> i32);
+        assert_eq!(ptr, (f as fn(i32) -> i32).to_ptr());
+
+        let g = unsafe { ptr.cast::<fn(i32) -> i32>() };
+        assert_eq!(g(2), 122);
+        assert_eq!(FnPointer::of(g as fn(i32) -> i32), ptr);
+    }
+
+    #[test]
+    fn struct_by_value() {
+        #[repr(C)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32
+        }
+
+        #[repr(C)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Large {
+            values: [i32; 8]
+        }
+
+        // The hooks are removed again before the next convention is tested, so it does not
+        // matter if functions with identical code get merged.
+        macro_rules! test_convention {
+            ($($abi:tt)*) => {{
+                $($abi)* fn f(p: Point, q: Point) -> Point { Point { x: p.x + q.x, y: p.y + q.y } }
+                $($abi)* fn d(p: Point, q: Point) -> Point { Point { x: p.x * q.x, y: p.y * q.y } }
+
+                $($abi)* fn g(l: Large, k: i32) -> Large { Large { values: [l.values[7] + k; 8] } }
+                $($abi)* fn e(l: Large, k: i32) -> Large { Large { values: [l.values[0] * k; 8] } }
+
+                let (p, q) = (Point { x: 2, y: 3 }, Point { x: 5, y: 7 });
+                let l = Large { values: [1, 2, 3, 4, 5, 6, 7, 8] };
+
+                let h = unsafe { Hook::<$($abi)* fn(Point, Point) -> Point>::create(f, d).unwrap() };
+                let i = unsafe { Hook::<$($abi)* fn(Large, i32) -> Large>::create(g, e).unwrap() };
+                h.enable().unwrap();
+                i.enable().unwrap();
+
+                assert_eq!(f(p, q), Point { x: 10, y: 21 });
+                assert_eq!(unsafe { h.trampoline()(p, q) }, Point { x: 7, y: 10 });
+                assert_eq!(g(l, 3), Large { values: [3; 8] });
+                assert_eq!(unsafe { i.trampoline()(l, 3) }, Large { values: [11; 8] });
+            }};
+        }
+
+        test_convention!();
+        test_convention!(extern "cdecl");
+        test_convention!(extern "stdcall");
+        test_convention!(extern "fastcall");
+        test_convention!(extern "C");
+        test_convention!(extern "system");
+        #[cfg(target_arch = "x86_64")]
+        test_convention!(extern "win64");
+    }
+
+    #[test]
+    fn signature() {
+        use function::{CallConv, SignatureInfo, signature_matches};
+
+        let target = <extern "system" fn(winapi::LPCWSTR) -> c_int>::signature();
+        assert_eq!(target, SignatureInfo { arity: 1, convention: CallConv::System });
+
+        assert!(signature_matches(&target, &<unsafe extern "system" fn(winapi::LPCWSTR) -> c_int>::signature()));
+        assert!(signature_matches(&target, &<extern "system" fn(i32) -> u8>::signature()));
+        assert!(!signature_matches(&target, &<extern "cdecl" fn(winapi::LPCWSTR) -> c_int>::signature()));
+        assert!(!signature_matches(&target, &<extern "system" fn(winapi::LPCWSTR, i32) -> c_int>::signature()));
+    }
+
+    #[test]
+    fn convention() {
+        use function::CallConv;
+
+        extern "stdcall" fn f() {}
+        fn g(_x: i32, _y: i32) {}
+
+        let f = f as extern "stdcall" fn();
+        assert_eq!(f.convention(), CallConv::Stdcall);
+        assert_eq!(f.arity(), 0);
+
+        let g = g as unsafe fn(i32, i32);
+        assert_eq!(g.convention(), CallConv::Rust);
+        assert_eq!(g.arity(), 2);
+
+        assert_eq!(<extern "fastcall" fn(i32)>::CONVENTION, CallConv::Fastcall);
+    }
+
+    #[test]
+    fn static_bypass() {
+        fn f(x: i32) -> i32 { x + 1 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| {
+                let _guard = h.bypass_scoped().unwrap();
+                f(x) * 2
+            };
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 4);
+        assert_eq!(f(1), 4);
+        {
+            let _guard = h.bypass_scoped().unwrap();
+            assert_eq!(f(1), 2);
+        }
+        assert_eq!(f(1), 4);
+        h.disable().unwrap();
+        {
+            let _guard = h.bypass_scoped().unwrap();
+            assert_eq!(f(1), 2);
+        }
+        assert_eq!(f(1), 2);
+    }
+
+    #[test]
+    fn static_no_catch() {
+        extern "C" fn f1(x: u32) -> u32 { x ^ 0x5555 }
+        extern "C" fn f2(x: u32) -> u32 { x ^ 0xaaaa }
+
+        static_hooks! {
+            impl guarded for f1: extern "C" fn(u32) -> u32 = |x| x.wrapping_add(1);
+            impl no_catch unguarded for f2: extern "C" fn(u32) -> u32 = |x| x.wrapping_add(1);
+        }
+
+        unsafe {
+            guarded.initialize().unwrap();
+            unguarded.initialize().unwrap();
+        }
+        guarded.enable().unwrap();
+        unguarded.enable().unwrap();
+
+        assert_eq!(f1(1), 2);
+        assert_eq!(f2(1), 2);
+        assert_eq!(guarded.call_real(1), 0x5554);
+        assert_eq!(unguarded.call_real(1), 0xaaab);
+    }
+
+    // Compare with `static_no_catch_detour` for the overhead of catching panics.
+    #[bench]
+    fn static_guarded_detour(b: &mut Bencher) {
+        extern "C" fn f(x: u32) -> u32 { x ^ 0x5151 }
+
+        static_hooks! {
+            impl h for f: extern "C" fn(u32) -> u32 = |x| x.wrapping_add(1);
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        b.iter(|| f(test::black_box(1)));
+    }
+
+    #[bench]
+    fn static_no_catch_detour(b: &mut Bencher) {
+        extern "C" fn f(x: u32) -> u32 { x ^ 0xa1a1 }
+
+        static_hooks! {
+            impl no_catch h for f: extern "C" fn(u32) -> u32 = |x| x.wrapping_add(1);
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        b.iter(|| f(test::black_box(1)));
+    }
+
+    #[test]
+    fn trampoline_fn() {
+        fn f(x: i32, y: i32) -> i32 { x * 173 + y }
+        fn d(x: i32, y: i32) -> i32 { x * 179 + y }
+
+        let h = unsafe { Hook::<fn(i32, i32) -> i32>::create(f, d).unwrap() };
+        h.enable().unwrap();
+        assert_eq!(f(1, 1), 180);
+
+        let trampoline = h.trampoline_fn();
+        assert_eq!(trampoline(1, 1), 174);
+        assert_eq!([1, 2].iter().map(|&x| trampoline(x, 0)).collect::<Vec<_>>(), vec![173, 346]);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn block_on_detour() {
+        use futures::{self, Future};
+        use blocking::Executor;
+
+        struct Trivial;
+
+        impl Executor for Trivial {
+            fn block_on<F: Future>(&self, future: F) -> ::std::result::Result<F::Item, F::Error> {
+                future.wait()
+            }
+        }
+
+        fn f(x: i32) -> i32 { x * 251 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = block_on(Trivial) |x| futures::finished::<i32, ()>(x * 257);
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 257);
+        h.disable().unwrap();
+        assert_eq!(f(1), 251);
+    }
+
+    #[test]
+    fn verify_intact() {
+        use std::slice;
+
+        fn f(x: i32) -> i32 { x * 239 }
+        fn d(x: i32) -> i32 { x * 241 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        let target = f as fn(i32) -> i32 as *mut u8;
+        assert_eq!(h.original_bytes(), unsafe { slice::from_raw_parts(target, 8) });
+        assert!(h.verify_intact());
+
+        h.enable().unwrap();
+        assert_eq!(f(1), 241);
+        assert!(h.verify_intact());
+
+        unsafe {
+            let mut protect = 0;
+            assert!(kernel32::VirtualProtect(target as winapi::LPVOID, 2, winapi::PAGE_EXECUTE_READWRITE, &mut protect) != 0);
+            ptr::write_bytes(target, 0x90, 2);
+            assert!(kernel32::VirtualProtect(target as winapi::LPVOID, 2, protect, &mut protect) != 0);
+        }
+        assert!(!h.verify_intact());
+
+        // Disabling the hook restores the original bytes.
+        h.disable().unwrap();
+        assert!(h.verify_intact());
+        assert_eq!(f(1), 239);
+    }
+
+    #[test]
+    fn call_original_safely() {
+        // Calls itself again through the hooked entry point, so a detour that calls the original
+        // function using `call_real()` would recurse forever.
+        fn f(x: i32) -> i32 {
+            let reenter = unsafe { ptr::read_volatile(&REENTER) };
+            reenter(x) + 167
+        }
+
+        static REENTER: fn(i32) -> i32 = f;
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| h.call_original_safely((x,)).unwrap_or(-x);
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 166);
+        assert_eq!(f(2), 165);
+        h.disable().unwrap();
+    }
+
+    #[test]
+    fn static_trampoline_cache() {
+        use std::sync::atomic::Ordering;
+
+        fn f(x: u32) -> u32 { x ^ 0x3333 }
+
+        static_hooks! {
+            impl h for f: fn(u32) -> u32;
+        }
+
+        let cached = || h.trampoline.load(Ordering::Acquire);
+
+        assert!(h.try_trampoline().is_none());
+        assert!(cached().is_null());
+        unsafe { h.initialize(|x| x).unwrap(); }
+        assert_eq!(cached(), h.try_hook().unwrap().trampoline.to_ptr().to_raw());
+        assert_eq!(h.trampoline()(1), f(1));
+
+        // The cache has to follow the hook when it is replaced.
+        unsafe { h.reinitialize(|x| x + 1).unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(cached(), h.try_hook().unwrap().trampoline.to_ptr().to_raw());
+        assert_eq!(h.trampoline()(1), 0x3332);
+        assert_eq!(f(1), 2);
+
+        unsafe { h.destroy().unwrap(); }
+        assert!(cached().is_null());
+        assert!(h.try_trampoline().is_none());
+    }
+
+    // Compare with `static_trampoline_lookup` for the benefit of caching the trampoline.
+    #[bench]
+    fn static_trampoline_cached(b: &mut Bencher) {
+        fn f(x: u32) -> u32 { x ^ 0x3131 }
+
+        static_hooks! {
+            impl h for f: fn(u32) -> u32;
+        }
+
+        unsafe { h.initialize(|x| x).unwrap(); }
+        b.iter(|| h.trampoline()(test::black_box(1)));
+    }
+
+    #[bench]
+    fn static_trampoline_lookup(b: &mut Bencher) {
+        fn f(x: u32) -> u32 { x ^ 0x1313 }
+
+        static_hooks! {
+            impl h for f: fn(u32) -> u32;
+        }
+
+        unsafe { h.initialize(|x| x).unwrap(); }
+        b.iter(|| h.try_trampoline().unwrap()(test::black_box(1)));
+    }
+
+    #[test]
+    fn static_panic_fallback() {
+        fn f(x: i32) -> i32 { x * 2 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| if x < 0 { panic!("negative argument") } else { x };
+            on_panic_return -1;
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(3), 3);
+        assert_eq!(f(-3), -1);
+        assert_eq!(f(4), 4);
+        h.disable().unwrap();
+        assert_eq!(f(-3), -6);
+    }
+
+    #[test]
+    fn static_error_fallback() {
+        fn f(x: i32) -> i32 { x * 107 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| if x < 0 { Err("negative argument") } else { Ok(x * 2) };
+            on_error_return -1;
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(3), 6);
+        assert_eq!(f(-3), -1);
+    }
+
+    #[cfg(not(all(target_arch = "x86", target_env = "msvc")))]
+    #[test]
+    fn static_panic_std_hook() {
+        use std::panic;
+        use std::sync::atomic::{ATOMIC_BOOL_INIT, AtomicBool, Ordering};
+
+        static CALLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+        fn f(x: i32) -> i32 { x * 67 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |_| panic!("detour panic for the standard hook");
+            on_panic_return 0;
+        }
+
+        // Other tests may panic concurrently, so pass everything else on to the previous hook.
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if info.payload().downcast_ref::<&str>() == Some(&"detour panic for the standard hook") {
+                CALLED.store(true, Ordering::SeqCst);
+            } else {
+                previous(info);
+            }
+        }));
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1), 0);
+        assert!(CALLED.load(Ordering::SeqCst));
+    }
+
+    #[cfg(feature = "increased_arity")]
+    #[test]
+    fn static_high_arity() {
+        #[allow(too_many_arguments)]
+        fn f(a0: i32, a1: i32, a2: i32, a3: i32, a4: i32, a5: i32, a6: i32, a7: i32, a8: i32, a9: i32,
+             b0: i32, b1: i32, b2: i32, b3: i32, b4: i32, b5: i32, b6: i32, b7: i32, b8: i32, b9: i32,
+             c0: i32, c1: i32, c2: i32, c3: i32, c4: i32, c5: i32, c6: i32, c7: i32, c8: i32, c9: i32) -> i32 {
+            a0 + a1 + a2 + a3 + a4 + a5 + a6 + a7 + a8 + a9 +
+            b0 + b1 + b2 + b3 + b4 + b5 + b6 + b7 + b8 + b9 +
+            c0 + c1 + c2 + c3 + c4 + c5 + c6 + c7 + c8 + c9
+        }
+
+        static_hooks! {
+            impl h for f: fn(i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                             i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                             i32, i32, i32, i32, i32, i32, i32, i32, i32, i32) -> i32 =
+                |a0, a1, a2, a3, a4, a5, a6, a7, a8, a9,
+                 b0, b1, b2, b3, b4, b5, b6, b7, b8, b9,
+                 c0, c1, c2, c3, c4, c5, c6, c7, c8, c9| -h.call_real(a0, a1, a2, a3, a4, a5, a6, a7, a8, a9,
+                                                                      b0, b1, b2, b3, b4, b5, b6, b7, b8, b9,
+                                                                      c0, c1, c2, c3, c4, c5, c6, c7, c8, c9);
+        }
+
+        unsafe { h.initialize().unwrap(); }
+        h.enable().unwrap();
+        assert_eq!(f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
+                     11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+                     21, 22, 23, 24, 25, 26, 27, 28, 29, 30), -465);
+    }
+
+    #[cfg(feature = "increased_arity")]
+    #[test]
+    fn static_arity_boundary() {
+        fn sum(values: &[i32]) -> i32 { values.iter().fold(0, |acc, x| acc + x) }
+
+        #[allow(too_many_arguments)]
+        fn f26(a0: i32, a1: i32, a2: i32, a3: i32, a4: i32, a5: i32, a6: i32, a7: i32, a8: i32, a9: i32,
+               b0: i32, b1: i32, b2: i32, b3: i32, b4: i32, b5: i32, b6: i32, b7: i32, b8: i32, b9: i32,
+               c0: i32, c1: i32, c2: i32, c3: i32, c4: i32, c5: i32) -> i32 {
+            sum(&[a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, b0, b1, b2, b3, b4, b5, b6, b7, b8, b9,
+                  c0, c1, c2, c3, c4, c5])
+        }
+
+        #[allow(too_many_arguments)]
+        fn f27(a0: i32, a1: i32, a2: i32, a3: i32, a4: i32, a5: i32, a6: i32, a7: i32, a8: i32, a9: i32,
+               b0: i32, b1: i32, b2: i32, b3: i32, b4: i32, b5: i32, b6: i32, b7: i32, b8: i32, b9: i32,
+               c0: i32, c1: i32, c2: i32, c3: i32, c4: i32, c5: i32, c6: i32) -> i32 {
+            sum(&[a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, b0, b1, b2, b3, b4, b5, b6, b7, b8, b9,
+                  c0, c1, c2, c3, c4, c5, c6]) * 2
+        }
+
+        static_hooks! {
+            impl h26 for f26: fn(i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                                 i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                                 i32, i32, i32, i32, i32, i32) -> i32;
+            impl h27 for f27: fn(i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                                 i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+                                 i32, i32, i32, i32, i32, i32, i32) -> i32;
+        }
+
+        unsafe {
+            h26.initialize(|_, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, z| z).unwrap();
+            h27.initialize(|_, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, _, z| z).unwrap();
+        }
+        h26.enable().unwrap();
+        h27.enable().unwrap();
+
+        assert_eq!(f26(1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 26), 26);
+        assert_eq!(f27(1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 27), 27);
+        assert_eq!(h27.call_real(1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1), 54);
+    }
+
+    #[test]
+    fn initialize_twice() {
+        assert_eq!(initialize(), Ok(()));
+        assert_eq!(initialize(), Ok(()));
+    }
+
+    #[test]
+    fn uninitialize_checked() {
+        use api::MockApi;
+        use super::uninitialize_with;
+
+        let api = MockApi::default();
+        assert_eq!(uninitialize_with(1, &api), Err(Error::HooksAlive));
+        assert!(api.calls().is_empty());
+
+        assert_eq!(uninitialize_with(0, &api), Ok(()));
+        assert_eq!(api.calls(), vec![("uninitialize", None)]);
+    }
+
+    #[test]
+    fn version() {
+        assert!(!minhook_version().is_empty());
+        assert!(!minhook_arch().is_empty());
+    }
+
+    #[test]
+    fn local_dynamic_loading() {
+        extern "system" fn get_file_version_info_size_w_detour(_filename: winapi::LPCWSTR, _handle: winapi::LPDWORD) -> winapi::DWORD {
+            42
+        }
+
+        let foo = OsStr::new("foo").encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        unsafe {
+            let (h, module) = Hook::<extern "system" fn(winapi::LPCWSTR, winapi::LPDWORD) -> winapi::DWORD>::create_api_loading(
+                "version.dll",
+                FunctionId::name("GetFileVersionInfoSizeW"),
+                get_file_version_info_size_w_detour).unwrap();
+            let target: extern "system" fn(winapi::LPCWSTR, winapi::LPDWORD) -> winapi::DWORD =
+                mem::transmute(resolve("version.dll", FunctionId::name("GetFileVersionInfoSizeW")).unwrap().to_raw());
+
+            assert_eq!(target(foo.as_ptr(), ptr::null_mut()), 0);
+            h.enable().unwrap();
+            assert_eq!(target(foo.as_ptr(), ptr::null_mut()), 42);
+
+            mem::drop(h);
+            mem::drop(module);
+        }
+    }
+
+    #[test]
+    fn static_is_initialized() {
+        fn f1(x: i32) -> i32 { x * 7 }
+        fn f2(x: i32) -> i32 { x * 11 }
+
+        static_hooks! {
+            impl h1 for f1: fn(i32) -> i32;
+            impl h2 for f2: fn(i32) -> i32 = |x| x;
+        }
+
+        assert!(!h1.is_initialized());
+        assert!(!h2.is_initialized());
+        unsafe {
+            h1.initialize(|x| x).unwrap();
+            h2.initialize().unwrap();
+        }
+        assert!(h1.is_initialized());
+        assert!(h2.is_initialized());
+    }
+
+    #[test]
+    fn static_try_trampoline() {
+        fn f(x: i32) -> i32 { x * 13 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32 = |x| x;
+        }
+
+        assert!(h.try_trampoline().is_none());
+        assert!(h.try_hook().is_none());
+        assert_eq!(h.try_call_real(2), None);
+        unsafe { h.initialize().unwrap(); }
+        assert!(h.try_trampoline().is_some());
+        assert_eq!(h.try_call_real(2), Some(26));
+    }
+
+    #[test]
+    fn chain() {
+        fn f(x: i32) -> i32 { x + 100 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32;
+        }
+
+        let chain = unsafe { HookChain::new(&h).unwrap() };
+        chain.enable().unwrap();
+        assert_eq!(f(3), 103);
+
+        let first = chain.push(|(x,), next| next.call((x + 1,)));
+        let second = chain.push(|(x,), next| next.call((x * 2,)));
+        assert_eq!(chain.len(), 2);
+        assert_eq!(f(3), 108);
+
+        mem::drop(first);
+        assert_eq!(f(3), 106);
+        second.remove();
+        assert!(chain.is_empty());
+        assert_eq!(f(3), 103);
+    }
+
+    #[test]
+    fn chain_map_detour() {
+        fn f(x: i32) -> i32 { x + 200 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32;
+        }
+
+        let chain = unsafe { HookChain::new(&h).unwrap() };
+        chain.enable().unwrap();
+
+        let link = chain.push(|(x,), next| next.call((x + 1,)));
+        assert_eq!(f(3), 204);
+
+        let link = link.map_detour(|args, next, old| old(args, next) + 1);
+        assert_eq!(f(3), 205);
+        assert_eq!(chain.len(), 1);
+
+        link.remove();
+        assert_eq!(f(3), 203);
+    }
+
+    #[test]
+    fn chain_thread_filter() {
+        use std::thread;
+
+        fn f(x: i32) -> i32 { x + 300 }
+
+        static_hooks! {
+            impl h for f: fn(i32) -> i32;
+        }
+
+        let chain = unsafe { HookChain::new(&h).unwrap() };
+        chain.enable().unwrap();
+        let _link = chain.push(|(x,), next| next.call((x * 2,)));
+
+        let current = unsafe { kernel32::GetCurrentThreadId() };
+        chain.set_thread_filter(move |thread| thread == current);
+        assert_eq!(f(3), 306);
+        assert_eq!(thread::spawn(|| f(3)).join().unwrap(), 303);
+
+        chain.clear_thread_filter();
+        assert_eq!(thread::spawn(|| f(3)).join().unwrap(), 306);
+    }
+
+    #[test]
+    fn chain_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<HookChain<fn(i32) -> i32>>();
+        assert_send_sync::<ChainLink<fn(i32) -> i32>>();
+        assert_send_sync::<Hook<extern "system" fn(winapi::LPCWSTR) -> c_int>>();
+    }
+
+    #[test]
+    fn local_send() {
+        use std::thread;
+
+        fn f(x: i32) -> i32 { x * 29 }
+        fn d(x: i32) -> i32 { x * 31 }
+
+        let h = unsafe { Hook::<fn(i32) -> i32>::create(f, d).unwrap() };
+        let h = thread::spawn(move || {
+            h.enable().unwrap();
+            assert_eq!(f(1), 31);
+            h.disable().unwrap();
+            assert_eq!(f(1), 29);
+            h.enable().unwrap();
+            h
+        }).join().unwrap();
+
+        assert_eq!(f(1), 31);
+        mem::drop(h);
+        assert_eq!(f(1), 29);
+    }
+}
@@ -4,8 +4,8 @@
 ///
 /// ```ignore
 /// // Creates a `StaticHookWithDefault`
-/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = FN_EXPR;
-/// #[ATTR]* pub? impl HOOK_VAR_NAME for "FUNCTION" in "MODULE": FN_TYPE = FN_EXPR;
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = enabled? FN_EXPR;
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for "FUNCTION" in "MODULE": FN_TYPE = enabled? FN_EXPR;
 ///
 /// // Creates a `StaticHook`
 /// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE;
@@ -22,6 +22,66 @@
 /// the detour `Fn` is a closure that does not need to capture any local variables
 /// or if the detour `Fn` is just a normal function. See `StaticHookWithDefault`.
 ///
+/// When `FN_EXPR` is the path of a free function, that function's body can call the original
+/// function through the hook variable, since the hook is just a static item:
+///
+/// ```ignore
+/// fn detour(x: i32) -> i32 {
+///     HOOK.call_real(x) + 1
+/// }
+///
+/// static_hooks! {
+///     impl HOOK for target: fn(i32) -> i32 = detour;
+/// }
+/// ```
+///
+/// Roughly speaking, this expands to a static `StaticHookWithDefault` holding a reference to
+/// `detour`, together with a private `#[inline(never)]` function of the target's type that
+/// forwards its arguments to `detour`. It is this private function that is actually installed
+/// as the MinHook detour.
+///
+/// If the `enabled` keyword precedes `FN_EXPR`, the hook is enabled right after it is installed
+/// by `initialize()`.
+///
+/// Any of the above definitions can be followed by `on_panic_return FALLBACK_EXPR;`. In that
+/// case any panic inside of the detour `Fn` is caught and `FALLBACK_EXPR` is returned to the
+/// caller of the target function instead, for both `extern` and non-`extern` functions. The
+/// panic handler of the `panic` submodule is not invoked for these hooks.
+///
+/// ```ignore
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = FN_EXPR; on_panic_return FALLBACK_EXPR;
+/// ```
+///
+/// Alternatively, the first two forms can be followed by `on_error_return FALLBACK_EXPR;`. In
+/// that case the detour `Fn` returns a `Result` of the target's return type and any error type,
+/// and `FALLBACK_EXPR` is returned to the caller of the target function whenever it returns
+/// `Err`. Panics are handled as usual for these hooks.
+///
+/// ```ignore
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = FN_EXPR; on_error_return FALLBACK_EXPR;
+/// ```
+///
+/// If the `futures` feature is enabled, `FN_EXPR` of the first two forms can be preceded by
+/// `block_on(EXECUTOR_EXPR)`. In that case the detour `Fn` returns a future of the target's return
+/// type, which is run to completion by the given `blocking::Executor` while the caller of the
+/// target function is blocked. A failed future causes a panic. See the `blocking` submodule for
+/// the risk of deadlocks.
+///
+/// ```ignore
+/// #[ATTR]* pub? impl HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = enabled? block_on(EXECUTOR_EXPR) FN_EXPR;
+/// ```
+///
+/// The `impl` keyword of any of the above definitions can be followed by `no_catch`. For
+/// `extern` functions this skips catching panics inside of the detour `Fn`, which removes the
+/// overhead of `std::panic::catch_unwind` from every call of the target function. This is only
+/// sound if the detour `Fn` never panics: unwinding across a foreign code boundary is undefined
+/// behavior. Upholding this is part of the safety contract of `initialize()`. A `no_catch` hook
+/// can not have an `on_panic_return` fallback.
+///
+/// ```ignore
+/// #[ATTR]* pub? impl no_catch HOOK_VAR_NAME for PATH::TO::TARGET: FN_TYPE = FN_EXPR;
+/// ```
+///
 /// The last two forms require a `Fn` to be supplied at the time of initialization of the
 /// static hook. In this case a closure that captures local variables can be supplied.
 /// See `StaticHook`.
@@ -67,6 +127,11 @@ macro_rules! static_hooks {
     // {
     //     static_hooks!(@parse_name_target ($($args)* (const)) | $($rest)*);
     // };
+    (@parse_mod ($($args:tt)*)
+              | no_catch $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_name_target ($($args)* (no_catch)) | $($rest)*);
+    };
     (@parse_mod ($($args:tt)*)
               | $($rest:tt)*) =>
     {
@@ -134,18 +199,50 @@ macro_rules! static_hooks {
 
     // Step 6: parse argument types and return type
     // Requires explicit look-ahead to satisfy rule for tokens following ty fragment specifier
+    (@parse_fn_value ($($args:tt)*)
+                   | = enabled block_on ($executor:expr) $value:expr ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_fn_fallback ($($args)* (block_on ($executor) ($value)) (true)) | $($rest)*);
+    };
+    (@parse_fn_value ($($args:tt)*)
+                   | = block_on ($executor:expr) $value:expr ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_fn_fallback ($($args)* (block_on ($executor) ($value)) (false)) | $($rest)*);
+    };
+    (@parse_fn_value ($($args:tt)*)
+                   | = enabled $value:expr ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_fn_fallback ($($args)* ($value) (true)) | $($rest)*);
+    };
     (@parse_fn_value ($($args:tt)*)
                    | = $value:expr ; $($rest:tt)*) =>
     {
-        static_hooks!(@parse_rest ($($args)* ($value)) | $($rest)*);
+        static_hooks!(@parse_fn_fallback ($($args)* ($value) (false)) | $($rest)*);
     };
     (@parse_fn_value ($($args:tt)*)
                    | ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_fn_fallback ($($args)* (!) (false)) | $($rest)*);
+    };
+
+    // Step 7: parse optional panic fallback value
+    (@parse_fn_fallback ($($args:tt)*)
+                      | on_panic_return $fallback:expr ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_rest ($($args)* ($fallback)) | $($rest)*);
+    };
+    (@parse_fn_fallback ($($args:tt)*)
+                      | on_error_return $fallback:expr ; $($rest:tt)*) =>
+    {
+        static_hooks!(@parse_rest ($($args)* (on_error_return ($fallback))) | $($rest)*);
+    };
+    (@parse_fn_fallback ($($args:tt)*)
+                      | $($rest:tt)*) =>
     {
         static_hooks!(@parse_rest ($($args)* (!)) | $($rest)*);
     };
 
-    // Step 6: parse rest and recurse
+    // Step 8: parse rest and recurse
     (@parse_rest ($($args:tt)*)
                | $($rest:tt)+) =>
     {
@@ -158,14 +255,19 @@ macro_rules! static_hooks {
         static_hooks!(@make $($args)*);
     };
 
-    // Step 7: parse rest and recurse
+    // Step 9: generate the hook
+    (@make ($($var_attr:meta)*) ($($var_mod:tt)*) (no_catch) ($var_name:ident) ($target:expr)
+           ($($fn_mod:tt)*) ($guard:tt) $($rest:tt)*) =>
+    {
+        static_hooks!(@make ($($var_attr)*) ($($var_mod)*) () ($var_name) ($target) ($($fn_mod)*) (NO_CATCH) $($rest)*);
+    };
     (@make ($($var_attr:meta)*) ($($var_mod:tt)*) ($($hook_mod:tt)*) ($var_name:ident) ($target:expr)
-           ($($fn_mod:tt)*) ($guard:tt) ($($arg_type:ty)*) ($return_type:ty) ($value:tt)) =>
+           ($($fn_mod:tt)*) ($guard:tt) ($($arg_type:ty)*) ($return_type:ty) ($value:tt) ($enabled:tt) ($fallback:tt)) =>
     {
         static_hooks!(@gen_arg_names (make_hook_var)
                                      (
                                          ($($var_attr)*) ($($var_mod)*) ($($hook_mod)*) ($var_name) ($target)
-                                         ($($fn_mod)*) ($guard) ($($arg_type)*) ($return_type) ($value)
+                                         ($($fn_mod)*) ($guard) ($($arg_type)*) ($return_type) ($value) ($enabled) ($fallback)
                                          ($($fn_mod)* fn ($($arg_type),*) -> $return_type)
                                      )
                                      ($($arg_type)*));
@@ -173,7 +275,7 @@ macro_rules! static_hooks {
 
     (@make_hook_var ($($arg_name:ident)*) ($($var_attr:meta)*) ($($var_mod:tt)*) ($($hook_mod:tt)*)
                     ($var_name:ident) ($target:expr) ($($fn_mod:tt)*) ($guard:tt)
-                    ($($arg_type:ty)*) ($return_type:ty) (!) ($fn_type:ty)) =>
+                    ($($arg_type:ty)*) ($return_type:ty) (!) ($enabled:tt) ($fallback:tt) ($fn_type:ty)) =>
     {
         static_hooks!(@make_item
             #[allow(non_upper_case_globals)]
@@ -181,16 +283,51 @@ macro_rules! static_hooks {
             $($var_mod)* static $var_name: $crate::StaticHook<$fn_type> = {
                 static __DATA: $crate::AtomicInitCell<$crate::__StaticHookInner<$fn_type>> = $crate::AtomicInitCell::new();
 
-                static_hooks!(@make_detour ($guard) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
+                static_hooks!(@make_detour ($guard) ($fallback) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
 
                 $crate::StaticHook::<$fn_type>::__new(&__DATA, $target, __detour)
             };
         );
     };
 
+    // The future returned by the default detour is turned into its value first.
+    (@make_hook_var ($($arg_name:ident)*) ($($var_attr:meta)*) ($($var_mod:tt)*) ($($hook_mod:tt)*)
+                    ($var_name:ident) ($target:expr) ($($fn_mod:tt)*) ($guard:tt)
+                    ($($arg_type:ty)*) ($return_type:ty) (block_on ($executor:expr) ($value:expr)) ($enabled:tt) ($fallback:tt) ($fn_type:ty)) =>
+    {
+        static_hooks!(@make_hook_var ($($arg_name)*) ($($var_attr)*) ($($var_mod)*) ($($hook_mod)*)
+                                     ($var_name) ($target) ($($fn_mod)*) ($guard)
+                                     ($($arg_type)*) ($return_type)
+                                     (|$($arg_name),*| $crate::blocking::block_on_detour(&$executor, $value($($arg_name),*)))
+                                     ($enabled) ($fallback) ($fn_type));
+    };
+
+    (@make_hook_var ($($arg_name:ident)*) ($($var_attr:meta)*) ($($var_mod:tt)*) ($($hook_mod:tt)*)
+                    ($var_name:ident) ($target:expr) ($($fn_mod:tt)*) ($guard:tt)
+                    ($($arg_type:ty)*) ($return_type:ty) ($value:tt) ($enabled:tt) (on_error_return ($fallback:expr)) ($fn_type:ty)) =>
+    {
+        static_hooks!(@make_item
+            #[allow(non_upper_case_globals)]
+            $(#[$var_attr])*
+            $($var_mod)* static $var_name: $crate::StaticHookWithDefault<$fn_type> = {
+                static __DATA: $crate::AtomicInitCell<$crate::__StaticHookInner<$fn_type>> = $crate::AtomicInitCell::new();
+
+                static_hooks!(@make_detour ($guard) (!) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
+
+                $crate::StaticHookWithDefault::<$fn_type>::__new(
+                    $crate::StaticHook::__new(&__DATA, $target, __detour),
+                    &|$($arg_name),*| match $value($($arg_name),*) {
+                        Ok(value) => value,
+                        Err(_) => $fallback
+                    },
+                    $enabled)
+            };
+        );
+    };
+
     (@make_hook_var ($($arg_name:ident)*) ($($var_attr:meta)*) ($($var_mod:tt)*) ($($hook_mod:tt)*)
                     ($var_name:ident) ($target:expr) ($($fn_mod:tt)*) ($guard:tt)
-                    ($($arg_type:ty)*) ($return_type:ty) ($value:tt) ($fn_type:ty)) =>
+                    ($($arg_type:ty)*) ($return_type:ty) ($value:tt) ($enabled:tt) ($fallback:tt) ($fn_type:ty)) =>
     {
         static_hooks!(@make_item
             #[allow(non_upper_case_globals)]
@@ -198,37 +335,67 @@ macro_rules! static_hooks {
             $($var_mod)* static $var_name: $crate::StaticHookWithDefault<$fn_type> = {
                 static __DATA: $crate::AtomicInitCell<$crate::__StaticHookInner<$fn_type>> = $crate::AtomicInitCell::new();
 
-                static_hooks!(@make_detour ($guard) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
+                static_hooks!(@make_detour ($guard) ($fallback) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
 
                 $crate::StaticHookWithDefault::<$fn_type>::__new(
                     $crate::StaticHook::__new(&__DATA, $target, __detour),
-                    &$value)
+                    &$value,
+                    $enabled)
             };
         );
     };
 
-    (@make_detour (GUARD) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
+    (@make_detour (GUARD) (!) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
         static_hooks!(@make_item
             #[inline(never)]
+            #[allow(unused_unsafe)]
             $($fn_mod)* fn __detour($($arg_name: $arg_type),*) -> $return_type {
                 ::std::panic::catch_unwind(|| {
-                    let &$crate::__StaticHookInner(_, ref closure) = __DATA.get().unwrap();
+                    let &$crate::__StaticHookInner(_, ref closure) = unsafe { __DATA.get_unchecked() };
                     closure($($arg_name),*)
                 }).unwrap_or_else(|payload| $crate::panic::__handle(module_path!(), stringify!($var_name), payload))
             }
         );
     };
 
-    (@make_detour (NO_GUARD) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
+    (@make_detour (NO_CATCH) (!) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
+        static_hooks!(@make_detour (NO_GUARD) (!) ($var_name) ($($fn_mod)*) ($($arg_name)*) ($($arg_type)*) ($return_type));
+    };
+
+    (@make_detour (NO_GUARD) (!) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
         static_hooks!(@make_item
             #[inline(never)]
+            #[allow(unused_unsafe)]
             $($fn_mod)* fn __detour($($arg_name: $arg_type),*) -> $return_type {
-                let &$crate::__StaticHookInner(_, ref closure) = __DATA.get().unwrap();
+                let &$crate::__StaticHookInner(_, ref closure) = unsafe { __DATA.get_unchecked() };
                 closure($($arg_name),*)
             }
         );
     };
 
+    // A fallback value requires catching panics, so it can not be combined with `no_catch`.
+    (@make_detour (GUARD) ($fallback:tt) $($rest:tt)*) => {
+        static_hooks!(@make_detour_fallback ($fallback) $($rest)*);
+    };
+    (@make_detour (NO_GUARD) ($fallback:tt) $($rest:tt)*) => {
+        static_hooks!(@make_detour_fallback ($fallback) $($rest)*);
+    };
+
+    // The user explicitly asked for the process to continue after a panic, so unwind safety is
+    // asserted here.
+    (@make_detour_fallback ($fallback:tt) ($var_name:ident) ($($fn_mod:tt)*) ($($arg_name:ident)*) ($($arg_type:ty)*) ($return_type:ty)) => {
+        static_hooks!(@make_item
+            #[inline(never)]
+            #[allow(unused_unsafe)]
+            $($fn_mod)* fn __detour($($arg_name: $arg_type),*) -> $return_type {
+                ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    let &$crate::__StaticHookInner(_, ref closure) = unsafe { __DATA.get_unchecked() };
+                    closure($($arg_name),*)
+                })).unwrap_or_else(|_| $fallback)
+            }
+        );
+    };
+
 
 
     // Makes sure items are interpreted correctly
@@ -236,23 +403,16 @@ macro_rules! static_hooks {
         $item
     };
 
-    // Generates a list of idents for each given token and invokes the macro by the given label passing through arguments
+    // Generates an ident for each given token and invokes the macro by the given label passing through arguments.
+    // Every `__arg` comes from a different expansion of the second rule, so hygiene keeps them apart and there is no
+    // upper limit on the number of arguments.
     (@gen_arg_names ($label:ident) ($($args:tt)*) ($($token:tt)*)) => {
-        static_hooks!(@gen_arg_names ($label)
-                                     ($($args)*)
-                                     (
-                                         __arg_0  __arg_1  __arg_2  __arg_3  __arg_4  __arg_5  __arg_6  __arg_7
-                                         __arg_8  __arg_9  __arg_10 __arg_11 __arg_12 __arg_13 __arg_14 __arg_15
-                                         __arg_16 __arg_17 __arg_18 __arg_19 __arg_20 __arg_21 __arg_22 __arg_23
-                                         __arg_24 __arg_25
-                                     )
-                                     ($($token)*)
-                                     ());
+        static_hooks!(@gen_arg_names ($label) ($($args)*) ($($token)*) ());
     };
-    (@gen_arg_names ($label:ident) ($($args:tt)*) ($hd_name:tt $($tl_name:tt)*) ($hd:tt $($tl:tt)*) ($($acc:tt)*) ) => {
-        static_hooks!(@gen_arg_names ($label) ($($args)*) ($($tl_name)*) ($($tl)*) ($($acc)* $hd_name));
+    (@gen_arg_names ($label:ident) ($($args:tt)*) ($hd:tt $($tl:tt)*) ($($acc:tt)*)) => {
+        static_hooks!(@gen_arg_names ($label) ($($args)*) ($($tl)*) ($($acc)* __arg));
     };
-    (@gen_arg_names ($label:ident) ($($args:tt)*) ($($name:tt)*) () ($($acc:tt)*)) => {
+    (@gen_arg_names ($label:ident) ($($args:tt)*) () ($($acc:tt)*)) => {
         static_hooks!(@$label ($($acc)*) $($args)*);
     };
 
@@ -262,32 +422,139 @@ macro_rules! static_hooks {
     };
 }
 
+/// Marks one or more functions as reliably hookable.
+///
+/// A hook on a target function only intercepts calls that actually reach the function. Calls to
+/// small functions are often inlined by the compiler, in which case the hook is installed
+/// successfully but the detour function never gets called. This macro applies
+/// `#[inline(never)]` to each of the given functions so that all calls to them go through their
+/// actual code.
+///
+/// This only helps for functions that are defined in your own crate, for example functions that
+/// are hooked in tests. It can not prevent functions in other crates or modules from being
+/// inlined.
+///
+/// ```ignore
+/// hookable! {
+///     fn target(x: i32) -> i32 {
+///         x + 1
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! hookable {
+    ($($item:item)*) => {
+        $(
+            #[inline(never)]
+            $item
+        )*
+    };
+}
+
+// Logs the outcome of a hook operation if the `logging` feature is enabled.
+#[cfg(feature = "logging")]
+macro_rules! log_result {
+    ($result:expr, $($arg:tt)+) => {
+        match *$result {
+            Ok(_) => debug!("{}", format_args!($($arg)+)),
+            Err(ref error) => warn!("{} failed: {}", format_args!($($arg)+), error)
+        }
+    };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! log_result {
+    ($result:expr, $($arg:tt)+) => { { let _ = $result; } };
+}
+
+// Times a hook operation and reports it to the metrics callback if the `metrics` feature is
+// enabled. The target is a closure that returns the target function given the result.
+#[cfg(feature = "metrics")]
+macro_rules! measure {
+    ($operation:ident, $target:expr, $body:expr) => {{
+        let start = ::std::time::Instant::now();
+        let result = $body;
+        ::metrics::__record(::metrics::Operation::$operation, $target(&result), start.elapsed(), &result);
+        result
+    }};
+}
+
+#[cfg(not(feature = "metrics"))]
+macro_rules! measure {
+    ($operation:ident, $target:expr, $body:expr) => { $body };
+}
+
 macro_rules! impl_hookable {
     (@recurse () ($($nm:ident : $ty:ident),*)) => {
         impl_hookable!(@impl_all ($($nm : $ty),*));
     };
     (@recurse ($hd_nm:ident : $hd_ty:ident $(, $tl_nm:ident : $tl_ty:ident)*) ($($nm:ident : $ty:ident),*)) => {
         impl_hookable!(@impl_all ($($nm : $ty),*));
+        // The object pointer takes up one argument, so methods have to have a lower arity.
+        impl_hookable!(@impl_method ($($nm : $ty),*));
         impl_hookable!(@recurse ($($tl_nm : $tl_ty),*) ($($nm : $ty,)* $hd_nm : $hd_ty));
     };
 
     (@impl_all ($($nm:ident : $ty:ident),*)) => {
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (                  fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "cdecl"    fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "stdcall"  fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "fastcall" fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "win64"    fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "C"        fn($($ty),*) -> Ret));
-        impl_hookable!(@impl_pair ($($nm : $ty),*) (extern "system"   fn($($ty),*) -> Ret));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Rust    ) (                 ));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Cdecl   ) (extern "cdecl"   ));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Stdcall ) (extern "stdcall" ));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Fastcall) (extern "fastcall"));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Win64   ) (extern "win64"   ));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (C       ) (extern "C"       ));
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (System  ) (extern "system"  ));
+        #[cfg(target_arch = "x86")]
+        impl_hookable!(@impl_pair ($($nm : $ty),*) (Thiscall) (extern "thiscall"));
+
+        #[cfg(target_arch = "x86")]
+        impl_hookable!(@impl_equivalent_abi ($($nm : $ty),*) (extern "system") (extern "stdcall"));
+        #[cfg(target_arch = "x86_64")]
+        impl_hookable!(@impl_equivalent_abi ($($nm : $ty),*) (extern "system") (extern "C"));
     };
 
-    (@impl_pair ($($nm:ident : $ty:ident),*) ($($fn_t:tt)*)) => {
-        impl_hookable!(@impl_fun ($($nm : $ty),*) ($($fn_t)*) (unsafe $($fn_t)*));
+    (@impl_method ($($nm:ident : $ty:ident),*)) => {
+        #[cfg(target_arch = "x86")]
+        impl_hookable!(@impl_method_abi ($($nm : $ty),*) (extern "thiscall"));
+        #[cfg(target_arch = "x86_64")]
+        impl_hookable!(@impl_method_abi ($($nm : $ty),*) (extern "C"));
     };
 
-    (@impl_fun ($($nm:ident : $ty:ident),*) ($safe_type:ty) ($unsafe_type:ty)) => {
-        impl_hookable!(@impl_core ($($nm : $ty),*) ($safe_type) ($unsafe_type));
-        impl_hookable!(@impl_core ($($nm : $ty),*) ($unsafe_type) ($unsafe_type));
+    (@impl_method_abi ($($nm:ident : $ty:ident),*) ($($abi:tt)*)) => {
+        unsafe impl<Class: 'static, Ret: 'static, $($ty: 'static),*> MethodArgs<Class, Ret> for ($($ty,)*) {
+            type Method = $($abi)* fn(*mut Class, $($ty),*) -> Ret;
+
+            #[allow(too_many_arguments)]
+            unsafe fn __call(method: Self::Method, this: *mut Class, ($($nm,)*): Self) -> Ret {
+                method(this, $($nm),*)
+            }
+        }
+    };
+
+    (@impl_equivalent_abi ($($nm:ident : $ty:ident),*) ($($abi1:tt)*) ($($abi2:tt)*)) => {
+        impl_hookable!(@impl_equivalent_abi_one ($($nm : $ty),*) ($($abi1)*) ($($abi2)*));
+        impl_hookable!(@impl_equivalent_abi_one ($($nm : $ty),*) ($($abi2)*) ($($abi1)*));
+    };
+
+    (@impl_equivalent_abi_one ($($nm:ident : $ty:ident),*) ($($target_abi:tt)*) ($($detour_abi:tt)*)) => {
+        unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWithEquivalentAbi<$($detour_abi)* fn($($ty),*) -> Ret>
+        for $($target_abi)* fn($($ty),*) -> Ret {}
+        unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWithEquivalentAbi<$($detour_abi)* fn($($ty),*) -> Ret>
+        for unsafe $($target_abi)* fn($($ty),*) -> Ret {}
+        unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWithEquivalentAbi<unsafe $($detour_abi)* fn($($ty),*) -> Ret>
+        for unsafe $($target_abi)* fn($($ty),*) -> Ret {}
+    };
+
+    (@impl_pair ($($nm:ident : $ty:ident),*) ($conv:ident) ($($abi:tt)*)) => {
+        impl_hookable!(@impl_fun ($($nm : $ty),*) ($conv) ($($abi)*) ($($abi)* fn($($ty),*) -> Ret) (unsafe $($abi)* fn($($ty),*) -> Ret));
+
+        impl_hookable!(@impl_hookable_with_diverging ($($nm : $ty),*) ($($abi)* fn($($ty),*) -> Ret) ($($abi)* fn($($ty),*) -> !));
+        impl_hookable!(@impl_hookable_with_diverging ($($nm : $ty),*) (unsafe $($abi)* fn($($ty),*) -> Ret) ($($abi)* fn($($ty),*) -> !));
+        impl_hookable!(@impl_hookable_with_diverging ($($nm : $ty),*) (unsafe $($abi)* fn($($ty),*) -> Ret) (unsafe $($abi)* fn($($ty),*) -> !));
+    };
+
+    (@impl_fun ($($nm:ident : $ty:ident),*) ($conv:ident) ($($abi:tt)*) ($safe_type:ty) ($unsafe_type:ty)) => {
+        impl_hookable!(@impl_core ($($nm : $ty),*) ($conv) ($($abi)*) ($safe_type) ($unsafe_type));
+        impl_hookable!(@impl_core ($($nm : $ty),*) ($conv) ($($abi)*) ($unsafe_type) ($unsafe_type));
 
         impl_hookable!(@impl_hookable_with ($($nm : $ty),*) ($unsafe_type) ($safe_type));
 
@@ -299,6 +566,10 @@ macro_rules! impl_hookable {
         unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWith<$detour> for $target {}
     };
 
+    (@impl_hookable_with_diverging ($($nm:ident : $ty:ident),*) ($target:ty) ($detour:ty)) => {
+        unsafe impl<Ret: 'static, $($ty: 'static),*> HookableWithDiverging<$detour> for $target {}
+    };
+
     (@impl_safe ($($nm:ident : $ty:ident),*) ($fn_type:ty)) => {
         impl<Ret: 'static, $($ty: 'static),*> Hook<$fn_type> {
             #[doc(hidden)]
@@ -307,6 +578,30 @@ macro_rules! impl_hookable {
                 (self.trampoline)($($nm),*)
             }
         }
+
+        impl<Ret: 'static, $($ty: 'static),*> DisabledHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub fn call_real(&self, $($nm : $ty),*) -> Ret {
+                self.0.call_real($($nm),*)
+            }
+        }
+
+        impl<Ret: 'static, $($ty: 'static),*> EnabledHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub fn call_real(&self, $($nm : $ty),*) -> Ret {
+                self.0.call_real($($nm),*)
+            }
+        }
+
+        impl<Ret: 'static, $($ty: 'static),*> StaticHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub fn try_call_real(&self, $($nm : $ty),*) -> Option<Ret> {
+                self.try_trampoline().map(|trampoline| trampoline($($nm),*))
+            }
+        }
     };
 
     (@impl_unsafe ($($nm:ident : $ty:ident),*) ($fn_type:ty)) => {
@@ -319,9 +614,33 @@ macro_rules! impl_hookable {
                 (self.trampoline)($($nm),*)
             }
         }
+
+        impl<Ret: 'static, $($ty: 'static),*> DisabledHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub unsafe fn call_real(&self, $($nm : $ty),*) -> Ret {
+                self.0.call_real($($nm),*)
+            }
+        }
+
+        impl<Ret: 'static, $($ty: 'static),*> EnabledHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub unsafe fn call_real(&self, $($nm : $ty),*) -> Ret {
+                self.0.call_real($($nm),*)
+            }
+        }
+
+        impl<Ret: 'static, $($ty: 'static),*> StaticHook<$fn_type> {
+            #[doc(hidden)]
+            #[allow(too_many_arguments)]
+            pub unsafe fn try_call_real(&self, $($nm : $ty),*) -> Option<Ret> {
+                self.try_trampoline().map(|trampoline| trampoline($($nm),*))
+            }
+        }
     };
 
-    (@impl_core ($($nm:ident : $ty:ident),*) ($fn_type:ty) ($unsafe_type:ty)) => {
+    (@impl_core ($($nm:ident : $ty:ident),*) ($conv:ident) ($($abi:tt)*) ($fn_type:ty) ($unsafe_type:ty)) => {
         unsafe impl<Ret: 'static, $($ty: 'static),*> Function for $fn_type {
             type Args = ($($ty,)*);
             type Output = Ret;
@@ -329,6 +648,8 @@ macro_rules! impl_hookable {
 
             const ARITY: usize = impl_hookable!(@count ($($ty)*));
 
+            const CONVENTION: CallConv = CallConv::$conv;
+
             unsafe fn from_ptr(ptr: FnPointer) -> Self {
                 mem::transmute(ptr.to_raw())
             }
@@ -341,6 +662,18 @@ macro_rules! impl_hookable {
             fn to_unsafe(&self) -> Self::Unsafe {
                 unsafe { mem::transmute(*self) }
             }
+
+            fn __closure_detour<Closure>() -> Self
+            where Closure: Fn<Self::Args, Output = Ret> + Send + Sync + 'static {
+                // There is one detour function for every closure type.
+                #[allow(too_many_arguments)]
+                $($abi)* fn detour<Closure, Ret, $($ty),*>($($nm : $ty),*) -> Ret
+                where Closure: Fn<($($ty,)*), Output = Ret> + Send + Sync + 'static {
+                    ::closure::call_closure::<Closure, _, _>(($($nm,)*))
+                }
+
+                detour::<Closure, Ret, $($ty),*>
+            }
         }
     };
 
@@ -0,0 +1,75 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use function::{Function, FnPointer, HookableWith, MethodArgs};
+use super::{Hook, HookResult};
+
+
+
+/// A hook on a method of a C++ class.
+///
+/// `C` is the type of the objects, `Args` is a tuple of the argument types of the method and
+/// `Ret` is its return type. The target and detour functions take a `*mut C` pointing to the
+/// object as their first argument, followed by the arguments of the method. See `MethodArgs` for
+/// the calling convention.
+pub struct ThisHook<C: 'static, Args, Ret: 'static>
+where Args: MethodArgs<C, Ret> {
+    hook: Hook<Args::Method>,
+    marker: PhantomData<fn(*mut C, Args) -> Ret>
+}
+
+impl<C: 'static, Args, Ret: 'static> ThisHook<C, Args, Ret>
+where Args: MethodArgs<C, Ret> {
+    /// Create a new hook given the function implementing a method and a compatible detour
+    /// function.
+    ///
+    /// The hook is disabled by default.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for [`Hook::create()`](struct.Hook.html#method.create).
+    pub unsafe fn create<D>(target: Args::Method, detour: D) -> HookResult<ThisHook<C, Args, Ret>>
+    where Args::Method: HookableWith<D>, D: Function {
+        Hook::create(target, detour).map(|hook| ThisHook {
+            hook: hook,
+            marker: PhantomData
+        })
+    }
+
+    /// Create a new hook for the virtual method at the given index of the virtual function table
+    /// of the given object, and a compatible detour function.
+    ///
+    /// The pointer to the virtual function table is expected at the start of the object, as laid
+    /// out by the Microsoft C++ compiler for classes with virtual methods. The hook affects all
+    /// objects that share the implementation of the method, not just the given object.
+    ///
+    /// # Safety
+    ///
+    /// The object must start with a pointer to a virtual function table with at least
+    /// `index + 1` entries, and the entry at `index` must be a method of type `Args::Method`.
+    ///
+    /// See also the documentation for [`Hook::create()`](struct.Hook.html#method.create).
+    pub unsafe fn create_virtual<D>(object: *const C, index: usize, detour: D) -> HookResult<ThisHook<C, Args, Ret>>
+    where Args::Method: HookableWith<D>, D: Function {
+        let vtable = *(object as *const *const FnPointer);
+        ThisHook::create(<Args::Method as Function>::from_ptr(*vtable.offset(index as isize)), detour)
+    }
+
+    /// Calls the original method for the given object with the given arguments.
+    ///
+    /// # Safety
+    ///
+    /// The object pointer must be valid for the original method.
+    pub unsafe fn call_original(&self, this: *mut C, args: Args) -> Ret {
+        Args::__call(self.hook.trampoline, this, args)
+    }
+}
+
+impl<C: 'static, Args, Ret: 'static> Deref for ThisHook<C, Args, Ret>
+where Args: MethodArgs<C, Ret> {
+    type Target = Hook<Args::Method>;
+
+    fn deref(&self) -> &Hook<Args::Method> {
+        &self.hook
+    }
+}
@@ -0,0 +1,55 @@
+//! Instrumentation of hook operations.
+//!
+//! This module is only available if the `metrics` feature is enabled. Without it, hook
+//! operations are not timed at all.
+
+use std::time::Duration;
+
+use function::FnPointer;
+use sync::StaticRwCell;
+use super::Result;
+
+
+
+/// A hook operation that is reported to the metrics callback.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// A hook was created.
+    Create,
+    /// A hook was enabled.
+    Enable,
+    /// A hook was disabled.
+    Disable
+}
+
+
+
+type Callback = Box<Fn(Operation, Option<FnPointer>, Duration, Result<()>) + Sync + Send>;
+
+static CALLBACK: StaticRwCell<Option<Callback>> = StaticRwCell::new(None);
+
+/// Registers a callback that is invoked after each hook operation, replacing any previously
+/// registered callback.
+///
+/// The callback receives the operation, the target function, the time the operation took and
+/// its result. The target function is `None` only if creating a hook using `Hook::create_api()`
+/// failed, since the target function is not known in that case.
+///
+/// The callback is a global resource. It must not create, enable or disable hooks itself.
+pub fn set_callback<F>(callback: F)
+where F: Fn(Operation, Option<FnPointer>, Duration, Result<()>) + Sync + Send + 'static {
+    let callback: Callback = Box::new(callback);
+    CALLBACK.set(Some(callback));
+}
+
+/// Unregisters the metrics callback, if any.
+pub fn clear_callback() {
+    CALLBACK.set(None);
+}
+
+#[doc(hidden)]
+pub fn __record<R>(operation: Operation, target: Option<FnPointer>, duration: Duration, result: &Result<R>) {
+    CALLBACK.with(|callback| if let Some(ref callback) = *callback {
+        callback(operation, target, duration, result.as_ref().map(|_| ()).map_err(|&error| error));
+    });
+}
@@ -0,0 +1,53 @@
+use std::ffi::OsStr;
+
+use {kernel32, winapi};
+
+use export::{self, Export};
+use super::{Error, Result, str_to_wstring};
+
+
+
+/// A handle to a loaded module that keeps the module loaded while it is alive.
+///
+/// Dropping a `Module` decrements the module's reference count, which unloads the module if no
+/// other references to it remain.
+#[derive(Debug)]
+pub struct Module(winapi::HMODULE);
+
+impl Module {
+    /// Loads the module with the given name.
+    ///
+    /// If the module is already loaded, its reference count is incremented instead.
+    pub fn load<M: AsRef<OsStr>>(name: M) -> Result<Module> {
+        let module_name = try!(str_to_wstring(name.as_ref()));
+
+        let handle = unsafe { kernel32::LoadLibraryW(module_name.as_ptr()) };
+        if handle.is_null() {
+            return Err(Error::ModuleNotFound);
+        }
+        Ok(Module(handle))
+    }
+
+    /// Returns the functions exported by this module, as listed in its export table.
+    ///
+    /// Exports that are forwarded to other modules are not included. See `resolve_forwarded()`
+    /// for resolving those.
+    pub fn exports(&self) -> Result<Vec<Export>> {
+        unsafe { export::exports(self.0) }
+    }
+
+    /// Returns the raw module handle, which is also the base address of the module.
+    pub fn handle(&self) -> winapi::HMODULE {
+        self.0
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe { kernel32::FreeLibrary(self.0); }
+    }
+}
+
+// Module handles are valid process-wide.
+unsafe impl Sync for Module {}
+unsafe impl Send for Module {}
@@ -1,9 +1,24 @@
 //! Panic handling for panics caught at foreign code boundaries in detour functions.
+//!
+//! The panic hook of the standard library, as set by `std::panic::set_hook`, runs as soon as a
+//! detour function panics, before the panic is caught. The usual diagnostic output, such as the
+//! panic message and a backtrace if `RUST_BACKTRACE` is set, is therefore already produced by the
+//! time the detour panic handler is invoked.
 
+#[cfg(feature = "backtrace")]
+use backtrace::Backtrace;
+#[cfg(not(feature = "libc"))]
+use kernel32;
+#[cfg(feature = "libc")]
 use libc;
 use std::any::Any;
+use std::borrow::Cow;
+#[cfg(feature = "backtrace")]
+use std::env;
 use std::io::{self, Write};
 use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use sync::StaticRwCell;
 
@@ -13,9 +28,15 @@ use sync::StaticRwCell;
 #[derive(Clone, Copy, Debug)]
 pub struct DetourPanicInfo<'a> {
     payload: &'a (Any + Send),
-    detour: &'a str
+    detour: &'a str,
+    backtrace: Option<&'a CapturedBacktrace>
 }
 
+#[cfg(feature = "backtrace")]
+type CapturedBacktrace = Backtrace;
+#[cfg(not(feature = "backtrace"))]
+type CapturedBacktrace = ();
+
 impl<'a> DetourPanicInfo<'a> {
     /// Returns the payload associated with the panic.
     ///
@@ -29,19 +50,37 @@ impl<'a> DetourPanicInfo<'a> {
     pub fn detour(&self) -> &str {
         self.detour
     }
+
+    /// Returns a backtrace of the detour function, if one was captured.
+    ///
+    /// Whether a backtrace is captured is decided by `DefaultHandlerConfig::capture_backtrace`,
+    /// as set with `set_default_handler_format()`. If that is `None`, a backtrace is captured if
+    /// the `RUST_BACKTRACE` environment variable is set to anything other than `0`.
+    ///
+    /// The backtrace is captured after the panic was caught, so it starts at the detour function
+    /// and leads up to the caller of the target function instead of to the origin of the panic.
+    /// The origin is included in the output of the standard panic hook.
+    ///
+    /// This method is only available if the `backtrace` feature is enabled.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace
+    }
 }
 
 
 
-static HANDLER: StaticRwCell<Option<Box<Fn(&DetourPanicInfo) + Sync + Send>>> = StaticRwCell::new(None);
+type Handler = Box<Fn(&DetourPanicInfo) + Sync + Send>;
 
-/// Registers a custom detour panic handler, replacing any that was previously
+static HANDLERS: StaticRwCell<Option<Vec<Handler>>> = StaticRwCell::new(None);
+
+/// Registers a custom detour panic handler, replacing any that were previously
 /// registered.
 ///
 /// The panic handler is invoked when an extern detour function panics just before
 /// the code would unwind into foreign code. The default handler prints a message
 /// to standard error and aborts the process to prevent further unwinding, but this behavior
-/// can be customized with the `set_handler` and `take_handler` functions.
+/// can be customized with the `set_handler`, `add_handler` and `take_handler` functions.
 ///
 /// The handler is provided with a `DetourPanicInfo` struct which contains information
 /// about the origin of the panic, including the payload passed to `panic!` and
@@ -52,41 +91,320 @@ static HANDLER: StaticRwCell<Option<Box<Fn(&DetourPanicInfo) + Sync + Send>>> =
 /// The panic handler is a global resource.
 pub fn set_handler<F>(handler: F)
 where F: Fn(&DetourPanicInfo) + Sync + Send + 'static {
-    HANDLER.set(Some(Box::new(handler)));
+    let handler: Handler = Box::new(handler);
+    HANDLERS.set(Some(vec![handler]));
+}
+
+/// Registers a detour panic handler that counts detour panics, replacing any that were
+/// previously registered.
+///
+/// Returns the counter, which is incremented once for every detour panic before the message of
+/// the default handler is printed. Handlers are `Fn`, so a custom handler that keeps any other
+/// state has to use atomics or a `Mutex` captured by the handler in the same way.
+pub fn set_counting_handler() -> Arc<AtomicUsize> {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let handler_counter = counter.clone();
+    set_handler(move |info| {
+        handler_counter.fetch_add(1, Ordering::SeqCst);
+        default_handler(info);
+    });
+    counter
+}
+
+/// Registers an additional custom detour panic handler.
+///
+/// The handlers are invoked in the order in which they were registered. The default handler is
+/// not invoked if any custom handler is registered.
+pub fn add_handler<F>(handler: F)
+where F: Fn(&DetourPanicInfo) + Sync + Send + 'static {
+    let handler: Handler = Box::new(handler);
+    HANDLERS.with_mut(|handlers| {
+        if let Some(ref mut list) = *handlers {
+            list.push(handler);
+            return;
+        }
+        *handlers = Some(vec![handler]);
+    });
 }
 
-/// Unregisters the current panic handler, returning it.
+/// Unregisters the current panic handlers, returning them as a single handler.
 ///
-/// If no custom handler is registered, the default handler will be returned.
-pub fn take_handler() -> Box<Fn(&DetourPanicInfo) + Sync + Send> {
-    HANDLER.take().unwrap_or_else(|| Box::new(default_handler))
+/// Returns `None` if no custom handler is registered, in which case the default handler is in
+/// use. The default handler itself is available as `default_handler`.
+pub fn take_handler() -> Option<Box<Fn(&DetourPanicInfo) + Sync + Send>> {
+    HANDLERS.take().and_then(|mut handlers| match handlers.len() {
+        0 => None,
+        1 => handlers.pop(),
+        _ => Some(Box::new(move |info: &DetourPanicInfo| call_all(&handlers, info)) as Handler)
+    })
 }
 
 #[doc(hidden)]
 pub fn __handle(path: &'static str, name: &'static str, payload: Box<Any + Send>) -> ! {
     let payload = AssertUnwindSafe(payload);
 
-    let _ = panic::catch_unwind(move || {
-        let full_path = format!("{}::{}", path, name);
-        let info = DetourPanicInfo {
-            payload: &**payload,
-            detour: &full_path
-        };
-
-        HANDLER.with(|handler| {
-            if let Some(ref handler) = *handler {
-                handler(&info);
-            } else {
-                default_handler(&info);
-            }
-        });
+    let _ = panic::catch_unwind(move || with_info(path, name, &**payload, call_handlers));
+
+    abort()
+}
+
+fn with_info<F: FnOnce(&DetourPanicInfo)>(path: &str, name: &str, payload: &(Any + Send), f: F) {
+    let full_path = format!("{}::{}", path, name);
+    let backtrace = capture_backtrace();
+    f(&DetourPanicInfo {
+        payload: payload,
+        detour: &full_path,
+        backtrace: backtrace.as_ref()
+    });
+}
+
+// Capturing and resolving a backtrace is expensive, so it is only done on request.
+#[cfg(feature = "backtrace")]
+fn capture_backtrace() -> Option<Backtrace> {
+    let configured = DEFAULT_CONFIG.with(|config| config.as_ref().and_then(|config| config.capture_backtrace));
+    let capture = configured.unwrap_or_else(|| match env::var_os("RUST_BACKTRACE") {
+        Some(ref value) => value != "0",
+        None => false
     });
 
+    if capture {
+        Some(Backtrace::new())
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "backtrace"))]
+fn capture_backtrace() -> Option<()> {
+    None
+}
+
+#[cfg(feature = "libc")]
+fn abort() -> ! {
     unsafe { libc::abort() }
 }
 
-fn default_handler(info: &DetourPanicInfo) {
-    let mut stderr = io::stderr();
-    let _ = writeln!(stderr, "The detour function for '{}' panicked. Aborting.", info.detour);
-    let _ = stderr.flush();
-}
\ No newline at end of file
+// Terminates the process immediately with the exit code used by `abort()` of the C runtime.
+#[cfg(not(feature = "libc"))]
+fn abort() -> ! {
+    unsafe {
+        kernel32::TerminateProcess(kernel32::GetCurrentProcess(), 3);
+    }
+    unreachable!()
+}
+
+fn call_handlers(info: &DetourPanicInfo) {
+    HANDLERS.with(|handlers| match *handlers {
+        Some(ref handlers) if !handlers.is_empty() => call_all(handlers, info),
+        _ => default_handler(info)
+    });
+}
+
+fn call_all(handlers: &[Handler], info: &DetourPanicInfo) {
+    for handler in handlers {
+        handler(info);
+    }
+}
+
+/// The format and output stream of the message printed by the default handler.
+pub struct DefaultHandlerConfig {
+    /// The message, without the trailing newline.
+    ///
+    /// Every occurrence of `{detour}` is replaced with the name of the hook and every occurrence
+    /// of `{payload}` with the payload of the panic if it is a `&str` or `String`, or with
+    /// `Box<Any>` otherwise.
+    pub format: Cow<'static, str>,
+    /// The stream the message is written to, or `None` for standard error.
+    ///
+    /// The stream is flushed after every message.
+    pub output: Option<Arc<Mutex<Write + Send>>>,
+    /// Whether a backtrace is captured for `DetourPanicInfo::backtrace()` before any handler is
+    /// invoked, or `None` to decide this based on the `RUST_BACKTRACE` environment variable.
+    ///
+    /// This has no effect unless the `backtrace` feature is enabled.
+    pub capture_backtrace: Option<bool>
+}
+
+impl Default for DefaultHandlerConfig {
+    fn default() -> DefaultHandlerConfig {
+        DefaultHandlerConfig {
+            format: Cow::Borrowed("The detour function for '{detour}' panicked. Aborting."),
+            output: None,
+            capture_backtrace: None
+        }
+    }
+}
+
+static DEFAULT_CONFIG: StaticRwCell<Option<DefaultHandlerConfig>> = StaticRwCell::new(None);
+
+/// Changes the format and output stream of the default handler, for example in order to include
+/// the panic message with `{payload}`.
+///
+/// This also applies to custom handlers that call `default_handler`. The configuration is a
+/// global resource.
+pub fn set_default_handler_format(config: DefaultHandlerConfig) {
+    DEFAULT_CONFIG.set(Some(config));
+}
+
+/// The detour panic handler that is used when no custom handler is registered.
+///
+/// It prints a message naming the hook to standard error, or to the stream and in the format
+/// set with `set_default_handler_format`. Custom handlers can call it to keep this output.
+pub fn default_handler(info: &DetourPanicInfo) {
+    DEFAULT_CONFIG.with(|config| match *config {
+        Some(ref config) => {
+            let message = format_message(&config.format, info);
+            match config.output {
+                Some(ref output) => {
+                    // A panic while the stream was locked must not suppress the message.
+                    let mut output = output.lock().unwrap_or_else(|error| error.into_inner());
+                    write_message(&mut *output, &message);
+                }
+                None => write_message(&mut io::stderr(), &message)
+            }
+        }
+        None => write_message(&mut io::stderr(), &format_message(&DefaultHandlerConfig::default().format, info))
+    });
+}
+
+fn format_message(format: &str, info: &DetourPanicInfo) -> String {
+    let payload = match info.payload.downcast_ref::<&str>() {
+        Some(payload) => *payload,
+        None => match info.payload.downcast_ref::<String>() {
+            Some(payload) => &payload[..],
+            None => "Box<Any>"
+        }
+    };
+    format.replace("{detour}", info.detour).replace("{payload}", payload)
+}
+
+fn write_message<W: Write + ?Sized>(output: &mut W, message: &str) {
+    let _ = writeln!(output, "{}", message);
+    let _ = output.flush();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use sync::StaticRwCell;
+    use super::*;
+    use super::{call_handlers, with_info};
+
+    // The handlers are a global resource, so tests that register handlers must not run at the
+    // same time.
+    static HANDLERS_LOCK: StaticRwCell<()> = StaticRwCell::new(());
+
+    #[test]
+    fn counting_handler() {
+        HANDLERS_LOCK.with_mut(|_| {
+            let counter = set_counting_handler();
+            let info = DetourPanicInfo {
+                payload: &"panic",
+                detour: "test::hook",
+                backtrace: None
+            };
+
+            call_handlers(&info);
+            call_handlers(&info);
+            assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+            let _ = take_handler();
+        });
+    }
+
+    #[test]
+    fn take_handler_none() {
+        HANDLERS_LOCK.with_mut(|_| {
+            let _ = take_handler();
+            assert!(take_handler().is_none());
+
+            set_handler(|_| ());
+            assert!(take_handler().is_some());
+            assert!(take_handler().is_none());
+        });
+    }
+
+    #[test]
+    fn multiple_handlers() {
+        HANDLERS_LOCK.with_mut(|_| {
+            let calls = Arc::new(AtomicUsize::new(0));
+
+            let first = calls.clone();
+            set_handler(move |info| {
+                assert_eq!(info.detour(), "test::hook");
+                first.fetch_add(1, Ordering::SeqCst);
+            });
+            let second = calls.clone();
+            add_handler(move |_| {
+                second.fetch_add(10, Ordering::SeqCst);
+            });
+
+            call_handlers(&DetourPanicInfo {
+                payload: &"panic",
+                detour: "test::hook",
+                backtrace: None
+            });
+            assert_eq!(calls.load(Ordering::SeqCst), 11);
+
+            let _ = take_handler();
+        });
+    }
+
+    #[test]
+    fn default_handler_format() {
+        HANDLERS_LOCK.with_mut(|_| {
+            let _ = take_handler();
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let stream: Arc<Mutex<Write + Send>> = output.clone();
+            set_default_handler_format(DefaultHandlerConfig {
+                format: "{detour}: {payload}".into(),
+                output: Some(stream),
+                ..DefaultHandlerConfig::default()
+            });
+
+            call_handlers(&DetourPanicInfo {
+                payload: &"panic message",
+                detour: "test::hook",
+                backtrace: None
+            });
+            call_handlers(&DetourPanicInfo {
+                payload: &String::from("owned message"),
+                detour: "test::hook",
+                backtrace: None
+            });
+            set_default_handler_format(DefaultHandlerConfig::default());
+
+            let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+            assert_eq!(output, "test::hook: panic message\ntest::hook: owned message\n");
+        });
+    }
+
+    // Backtraces can not be captured reliably on 32-bit MSVC targets.
+    #[cfg(all(feature = "backtrace", not(all(target_arch = "x86", target_env = "msvc"))))]
+    #[test]
+    fn backtrace() {
+        HANDLERS_LOCK.with_mut(|_| {
+            set_default_handler_format(DefaultHandlerConfig {
+                capture_backtrace: Some(true),
+                ..DefaultHandlerConfig::default()
+            });
+            let mut frames = 0;
+            with_info("test", "hook", &"panic", |info| {
+                frames = info.backtrace().expect("no backtrace captured").frames().len();
+            });
+            assert!(frames > 0);
+
+            set_default_handler_format(DefaultHandlerConfig {
+                capture_backtrace: Some(false),
+                ..DefaultHandlerConfig::default()
+            });
+            with_info("test", "hook", &"panic", |info| assert!(info.backtrace().is_none()));
+
+            set_default_handler_format(DefaultHandlerConfig::default());
+        });
+    }
+}
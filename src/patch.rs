@@ -0,0 +1,203 @@
+use std::{mem, ptr, slice};
+
+use ffi;
+use function::FnPointer;
+use super::{Error, Result};
+
+
+
+// The number of bytes saved of each target function. This covers the jump written by MinHook.
+pub const SAVED_BYTES: usize = 8;
+
+// The size of the relative jump MinHook patches the target function with.
+const JMP_REL32_SIZE: usize = 5;
+// The size of the short jump used for hot-patching.
+const JMP_REL8_SIZE: usize = 2;
+
+const JMP_REL32: u8 = 0xe9;
+const JMP_REL8: u8 = 0xeb;
+#[cfg(target_arch = "x86_64")]
+const JMP_ABS_INDIRECT: [u8; 6] = [0xff, 0x25, 0x00, 0x00, 0x00, 0x00];
+
+
+const RET_IMM16: u8 = 0xc2;
+const RET: u8 = 0xc3;
+
+
+
+// The number of bytes written by `write_relay()`.
+#[cfg(target_arch = "x86_64")]
+pub const RELAY_SIZE: usize = 14;
+#[cfg(not(target_arch = "x86_64"))]
+pub const RELAY_SIZE: usize = 5;
+
+// The number of bytes written by `write_thunk()`.
+pub const THUNK_SIZE: usize = STORE_SIZE + RELAY_SIZE;
+#[cfg(target_arch = "x86_64")]
+const STORE_SIZE: usize = 19;
+#[cfg(not(target_arch = "x86_64"))]
+const STORE_SIZE: usize = 11;
+
+// The offset of the TLS slots in the thread environment block.
+#[cfg(target_arch = "x86_64")]
+const TEB_TLS_SLOTS: u32 = 0x1480;
+#[cfg(not(target_arch = "x86_64"))]
+const TEB_TLS_SLOTS: u32 = 0xe10;
+
+
+
+pub unsafe fn read(address: FnPointer) -> [u8; SAVED_BYTES] {
+    let mut bytes = [0; SAVED_BYTES];
+    bytes.copy_from_slice(slice::from_raw_parts(address.to_raw() as *const u8, SAVED_BYTES));
+    bytes
+}
+
+// Returns a jump to the detour function to be placed at the given address. On x64 this is an
+// indirect jump through an absolute address following it, so that the detour function can be
+// anywhere.
+#[cfg(target_arch = "x86_64")]
+pub fn relay_code(_relay: *const u8, detour: FnPointer) -> [u8; RELAY_SIZE] {
+    let address: [u8; 8] = unsafe { mem::transmute((detour.addr() as u64).to_le()) };
+    let mut code = [0; RELAY_SIZE];
+    code[..JMP_ABS_INDIRECT.len()].copy_from_slice(&JMP_ABS_INDIRECT);
+    code[JMP_ABS_INDIRECT.len()..].copy_from_slice(&address);
+    code
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn relay_code(relay: *const u8, detour: FnPointer) -> [u8; RELAY_SIZE] {
+    let offset = (detour.addr() as i32).wrapping_sub((relay as i32).wrapping_add(JMP_REL32_SIZE as i32));
+    let offset: [u8; 4] = unsafe { mem::transmute(offset.to_le()) };
+    [JMP_REL32, offset[0], offset[1], offset[2], offset[3]]
+}
+
+pub unsafe fn read_relay(relay: *const u8) -> [u8; RELAY_SIZE] {
+    let mut code = [0; RELAY_SIZE];
+    code.copy_from_slice(slice::from_raw_parts(relay, RELAY_SIZE));
+    code
+}
+
+unsafe fn write_relay(relay: *mut u8, detour: FnPointer) {
+    let code = relay_code(relay, detour);
+    ptr::copy_nonoverlapping(code.as_ptr(), relay, RELAY_SIZE);
+}
+
+// Writes code that stores the given value in the given TLS slot of the current thread and then
+// jumps to the detour function. The slot has to be one of the first 64, which are stored in the
+// thread environment block itself. Only `rax` or `eax` is modified, which no supported calling
+// convention uses to pass arguments.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn write_thunk(thunk: *mut u8, slot: u32, value: usize, detour: FnPointer) {
+    let value: [u8; 8] = mem::transmute((value as u64).to_le());
+    let offset: [u8; 4] = mem::transmute((TEB_TLS_SLOTS + slot * 8).to_le());
+
+    let mut code = Vec::with_capacity(STORE_SIZE);
+    // mov rax, value
+    code.extend_from_slice(&[0x48, 0xb8]);
+    code.extend_from_slice(&value);
+    // mov gs:[offset], rax
+    code.extend_from_slice(&[0x65, 0x48, 0x89, 0x04, 0x25]);
+    code.extend_from_slice(&offset);
+
+    ptr::copy_nonoverlapping(code.as_ptr(), thunk, STORE_SIZE);
+    write_relay(thunk.offset(STORE_SIZE as isize), detour);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub unsafe fn write_thunk(thunk: *mut u8, slot: u32, value: usize, detour: FnPointer) {
+    let value: [u8; 4] = mem::transmute((value as u32).to_le());
+    let offset: [u8; 4] = mem::transmute((TEB_TLS_SLOTS + slot * 4).to_le());
+
+    let mut code = Vec::with_capacity(STORE_SIZE);
+    // mov eax, value
+    code.push(0xb8);
+    code.extend_from_slice(&value);
+    // mov fs:[offset], eax
+    code.extend_from_slice(&[0x64, 0xa3]);
+    code.extend_from_slice(&offset);
+
+    ptr::copy_nonoverlapping(code.as_ptr(), thunk, STORE_SIZE);
+    write_relay(thunk.offset(STORE_SIZE as isize), detour);
+}
+
+// MinHook patches the target function with a relative jump. If the function is hot-patchable, it
+// patches it with a short jump to a relative jump in the padding before the function instead.
+// On x64 the relative jump leads to a relay function that jumps to the detour function. The same
+// holds for hooks with a relay on any architecture.
+pub unsafe fn jumps_to(target: FnPointer, detour: FnPointer) -> bool {
+    let mut address = target.to_raw() as *const u8;
+    if *address == JMP_REL8 {
+        address = address.offset(2 + *address.offset(1) as i8 as isize);
+    }
+    if *address != JMP_REL32 {
+        return false;
+    }
+
+    let mut offset = [0u8; 4];
+    ptr::copy_nonoverlapping(address.offset(1), offset.as_mut_ptr(), 4);
+    let destination = address.offset(5 + i32::from_le(mem::transmute(offset)) as isize);
+
+    destination == detour.to_raw() as *const u8 || relays_to(destination, detour)
+}
+
+// On x64 the relay can also be the one written by MinHook, which uses the same indirect jump.
+unsafe fn relays_to(relay: *const u8, detour: FnPointer) -> bool {
+    read_relay(relay) == relay_code(relay, detour)
+}
+
+// Mirrors the checks MinHook performs while creating the trampoline: the instructions that are
+// overwritten by the jump have to be decodable and the function may not return or jump away
+// before the end of the jump, unless it is followed by padding or can be hot-patched.
+pub unsafe fn check_patchable(target: FnPointer) -> Result<()> {
+    let code = target.to_raw() as *const u8;
+
+    let mut size = 0;
+    while size < JMP_REL32_SIZE {
+        let mut instruction = ffi::hde_t::default();
+        let len = ffi::hde_disasm(code.offset(size as isize) as *const _, &mut instruction) as usize;
+        if instruction.flags & ffi::F_ERROR != 0 {
+            return Err(Error::InvalidInstruction { offset: size as u32 });
+        }
+        size += len;
+
+        match instruction.opcode {
+            RET | RET_IMM16 | JMP_REL32 | JMP_REL8 if size < JMP_REL32_SIZE => {
+                return check_remainder(code, size);
+            }
+            _ => ()
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn check_remainder(code: *const u8, size: usize) -> Result<()> {
+    if is_padding(code.offset(size as isize), JMP_REL32_SIZE - size) {
+        return Ok(());
+    }
+
+    // Hot-patching needs room for a short jump in the function and a relative jump in the
+    // padding above it.
+    let above = code.offset(-(JMP_REL32_SIZE as isize));
+    let fits_short_jump = size >= JMP_REL8_SIZE ||
+                          is_padding(code.offset(size as isize), JMP_REL8_SIZE - size);
+    if fits_short_jump && ::check_executable(FnPointer::from_raw(above as *mut _)).is_ok() &&
+       is_padding(above, JMP_REL32_SIZE) {
+        return Ok(());
+    }
+
+    Err(Error::FunctionTooSmall { size: size as u32 })
+}
+
+// Padding consists of a single repeated `int 3`, `nop` or zero byte. No bytes at all are trivially
+// padding.
+unsafe fn is_padding(code: *const u8, size: usize) -> bool {
+    let bytes = slice::from_raw_parts(code, size);
+    match bytes.first() {
+        Some(&first) if first == 0x00 || first == 0x90 || first == 0xcc => {
+            bytes.iter().all(|&byte| byte == first)
+        }
+        Some(_) => false,
+        None => true
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(feature = "serde")]
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use function::{Function, HookableWith};
+#[cfg(feature = "serde")]
+use super::FunctionId;
+use super::{Hook, HookResult, OwnedFunctionId};
+
+
+
+/// A description of a hook on an exported function, without the hook itself.
+///
+/// A spec only records where a hook should be placed and whether it should be enabled, so it can
+/// be saved and restored when the `serde` feature is enabled. The detour function is provided
+/// when the hook is installed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HookSpec {
+    /// The name of the module that exports the target function.
+    pub module: String,
+    /// The identifier of the target function.
+    pub function: OwnedFunctionId,
+    /// Whether the hook is enabled when it is installed.
+    pub enabled: bool
+}
+
+impl HookSpec {
+    /// Creates a hook as described by this spec using the given detour function, and enables it
+    /// if requested.
+    ///
+    /// # Safety
+    ///
+    /// See documentation for [`Hook::create_api()`](struct.Hook.html#method.create_api).
+    pub unsafe fn install<T, D>(&self, detour: D) -> HookResult<Hook<T>>
+    where T: Function + HookableWith<D>, D: Function {
+        let hook = try!(Hook::create_api(&self.module, self.function.as_function_id(), detour));
+        if self.enabled {
+            try!(hook.enable());
+        }
+        Ok(hook)
+    }
+}
+
+
+
+// The traits are implemented by hand, because `serde_derive` does not build on the nightly
+// compiler that this crate requires.
+
+/// Serializes the spec as a struct with the fields `module`, `function` and `enabled`.
+#[cfg(feature = "serde")]
+impl Serialize for HookSpec {
+    fn serialize<S: Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let mut state = try!(serializer.serialize_struct("HookSpec", 3));
+        try!(serializer.serialize_struct_elt(&mut state, "module", &self.module));
+        try!(serializer.serialize_struct_elt(&mut state, "function", &self.function));
+        try!(serializer.serialize_struct_elt(&mut state, "enabled", self.enabled));
+        serializer.serialize_struct_end(state)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Deserialize for HookSpec {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<HookSpec, D::Error> {
+        const FIELDS: &'static [&'static str] = &["module", "function", "enabled"];
+        deserializer.deserialize_struct("HookSpec", FIELDS, HookSpecVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct HookSpecVisitor;
+
+#[cfg(feature = "serde")]
+impl de::Visitor for HookSpecVisitor {
+    type Value = HookSpec;
+
+    fn visit_map<V: de::MapVisitor>(&mut self, mut visitor: V) -> Result<HookSpec, V::Error> {
+        let mut module = None;
+        let mut function = None;
+        let mut enabled = None;
+
+        while let Some(key) = try!(visitor.visit_key::<String>()) {
+            match &key[..] {
+                "module" => module = Some(try!(visitor.visit_value())),
+                "function" => function = Some(try!(visitor.visit_value())),
+                "enabled" => enabled = Some(try!(visitor.visit_value())),
+                _ => return Err(de::Error::unknown_field(&key))
+            }
+        }
+        try!(visitor.end());
+
+        Ok(HookSpec {
+            module: try!(module.map_or_else(|| visitor.missing_field("module"), Ok)),
+            function: try!(function.map_or_else(|| visitor.missing_field("function"), Ok)),
+            enabled: try!(enabled.map_or_else(|| visitor.missing_field("enabled"), Ok))
+        })
+    }
+}
+
+/// Serializes the identifier as a string in the format accepted by `FunctionId::parse()`.
+#[cfg(feature = "serde")]
+impl<'a> Serialize for FunctionId<'a> {
+    fn serialize<S: Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Serializes the identifier as a string in the format accepted by `FunctionId::parse()`.
+#[cfg(feature = "serde")]
+impl Serialize for OwnedFunctionId {
+    fn serialize<S: Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.as_function_id().serialize(serializer)
+    }
+}
+
+/// Deserializes the identifier from a string following the same rules as `FunctionId::parse()`.
+#[cfg(feature = "serde")]
+impl Deserialize for OwnedFunctionId {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<OwnedFunctionId, D::Error> {
+        deserializer.deserialize_str(FunctionIdVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct FunctionIdVisitor;
+
+#[cfg(feature = "serde")]
+impl de::Visitor for FunctionIdVisitor {
+    type Value = OwnedFunctionId;
+
+    fn visit_str<E: de::Error>(&mut self, string: &str) -> Result<OwnedFunctionId, E> {
+        OwnedFunctionId::from_str(string).map_err(|error| E::custom(error.to_string()))
+    }
+}
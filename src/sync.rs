@@ -1,12 +1,29 @@
 use std::{mem, ptr};
+#[cfg(feature = "lazy_static")]
 use std::cell::RefCell;
+#[cfg(not(feature = "lazy_static"))]
+use std::cell::UnsafeCell;
+#[cfg(not(feature = "lazy_static"))]
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "lazy_static")]
 use std::sync::RwLock;
+#[cfg(not(feature = "lazy_static"))]
+use std::sync::LockResult;
+#[cfg(not(feature = "lazy_static"))]
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(feature = "lazy_static"))]
+use std::thread;
 
+#[cfg(feature = "lazy_static")]
 use lazy_static::lazy::Lazy;
 
 
 
+// The pointer is only ever set to a fully constructed box, so storing it with `Release` ordering
+// and loading it with `Acquire` ordering is enough to make the contents of the box visible to
+// any thread that sees the pointer. No ordering with respect to other atomics is needed, so
+// `SeqCst` is not required.
 #[doc(hidden)]
 pub struct AtomicInitCell<T>(AtomicPtr<T>);
 
@@ -19,16 +36,56 @@ impl<T> AtomicInitCell<T> {
     #[doc(hidden)]
     pub fn initialize(&self, value: T) -> Result<(), ()> {
         let mut boxed = Box::new(value);
-        if !self.0.compare_and_swap(ptr::null_mut(), &mut *boxed, Ordering::SeqCst).is_null() {
+        if !self.0.compare_and_swap(ptr::null_mut(), &mut *boxed, Ordering::AcqRel).is_null() {
             return Err(());
         }
         mem::forget(boxed);
         Ok(())
     }
 
+    /// Replaces the value in this cell, returning the previous value if the cell was initialized.
+    ///
+    /// # Safety
+    ///
+    /// References previously returned by `get()` point into the returned box. The caller has to
+    /// make sure that none of these references are used anymore before the box is dropped,
+    /// otherwise it should be leaked.
+    pub unsafe fn replace(&self, value: T) -> Option<Box<T>> {
+        Self::into_box(self.0.swap(Box::into_raw(Box::new(value)), Ordering::AcqRel))
+    }
+
+    /// Takes the value out of this cell, leaving it uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// See `replace()`.
+    pub unsafe fn take(&self) -> Option<Box<T>> {
+        Self::into_box(self.0.swap(ptr::null_mut(), Ordering::AcqRel))
+    }
+
+    unsafe fn into_box(data: *mut T) -> Option<Box<T>> {
+        if data.is_null() {
+            return None;
+        }
+        Some(Box::from_raw(data))
+    }
+
+    /// Returns a reference to the value in this cell without checking whether the cell is
+    /// initialized.
+    ///
+    /// # Safety
+    ///
+    /// The cell has to be initialized. This is only checked in debug builds.
+    #[doc(hidden)]
+    pub unsafe fn get_unchecked(&self) -> &'static T {
+        let data = self.0.load(Ordering::Acquire);
+        debug_assert!(!data.is_null(), "attempt to access uninitialized cell");
+        &*data
+    }
+
     #[doc(hidden)]
     pub fn get(&self) -> Option<&'static T> {
-        let data = self.0.load(Ordering::SeqCst);
+        let data = self.0.load(Ordering::Acquire);
         if data.is_null() {
             return None;
         }
@@ -38,11 +95,13 @@ impl<T> AtomicInitCell<T> {
 
 
 
+#[cfg(feature = "lazy_static")]
 pub struct StaticRwCell<T: Send + Sync> {
     init: RefCell<Option<T>>,
     lock: Lazy<RwLock<T>>
 }
 
+#[cfg(feature = "lazy_static")]
 impl<T: Send + Sync> StaticRwCell<T> {
     pub const fn new(value: T) -> StaticRwCell<T> {
         StaticRwCell {
@@ -65,8 +124,15 @@ impl<T: Send + Sync> StaticRwCell<T> {
         let data = self.lock().read().unwrap();
         f(&*data)
     }
+
+    pub fn with_mut<F, R>(&'static self, f: F) -> R
+    where F: FnOnce(&mut T) -> R {
+        let mut data = self.lock().write().unwrap();
+        f(&mut *data)
+    }
 }
 
+#[cfg(feature = "lazy_static")]
 impl<T: Send + Sync> StaticRwCell<Option<T>> {
     pub fn take(&'static self) -> Option<T> {
         let mut data = self.lock().write().unwrap();
@@ -74,5 +140,124 @@ impl<T: Send + Sync> StaticRwCell<Option<T>> {
     }
 }
 
+#[cfg(feature = "lazy_static")]
 unsafe impl<T: Send + Sync> Sync for StaticRwCell<T> {}
-unsafe impl<T: Send + Sync> Send for StaticRwCell<T> {}
\ No newline at end of file
+#[cfg(feature = "lazy_static")]
+unsafe impl<T: Send + Sync> Send for StaticRwCell<T> {}
+
+
+
+// Without `lazy_static` the cell is a spinlock, so readers exclude each other as well.
+#[cfg(not(feature = "lazy_static"))]
+pub struct StaticRwCell<T: Send + Sync>(SpinLock<T>);
+
+#[cfg(not(feature = "lazy_static"))]
+impl<T: Send + Sync> StaticRwCell<T> {
+    pub const fn new(value: T) -> StaticRwCell<T> {
+        StaticRwCell(SpinLock::with_value(value))
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<T: Send + Sync + Default> StaticRwCell<T> {
+    pub fn set(&'static self, value: T) {
+        let mut data = self.0.lock().unwrap();
+        *data = value;
+    }
+
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where F: FnOnce(&T) -> R {
+        let data = self.0.lock().unwrap();
+        f(&*data)
+    }
+
+    pub fn with_mut<F, R>(&'static self, f: F) -> R
+    where F: FnOnce(&mut T) -> R {
+        let mut data = self.0.lock().unwrap();
+        f(&mut *data)
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<T: Send + Sync> StaticRwCell<Option<T>> {
+    pub fn take(&'static self) -> Option<T> {
+        let mut data = self.0.lock().unwrap();
+        data.take()
+    }
+}
+
+
+
+// A mutex that can be used in a static without `lazy_static`. Most values can not be created in
+// a constant, so unless a value is given the value is created using `Default` when the lock is
+// first acquired. The lock is never poisoned, `lock()` returns a `LockResult` only to match
+// `Mutex`.
+#[cfg(not(feature = "lazy_static"))]
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<Option<T>>
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<T> SpinLock<T> {
+    pub const fn new() -> SpinLock<T> {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(None)
+        }
+    }
+
+    pub const fn with_value(value: T) -> SpinLock<T> {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(Some(value))
+        }
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<T: Default> SpinLock<T> {
+    pub fn lock(&self) -> LockResult<SpinLockGuard<T>> {
+        while self.locked.compare_and_swap(false, true, Ordering::Acquire) {
+            thread::yield_now();
+        }
+
+        let guard = SpinLockGuard(self);
+        let data = unsafe { &mut *self.data.get() };
+        if data.is_none() {
+            *data = Some(T::default());
+        }
+        Ok(guard)
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+#[cfg(not(feature = "lazy_static"))]
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+#[cfg(not(feature = "lazy_static"))]
+pub struct SpinLockGuard<'a, T: 'a>(&'a SpinLock<T>);
+
+#[cfg(not(feature = "lazy_static"))]
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { (*self.0.data.get()).as_ref().unwrap() }
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { (*self.0.data.get()).as_mut().unwrap() }
+    }
+}
+
+#[cfg(not(feature = "lazy_static"))]
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.0.locked.store(false, Ordering::Release);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::ops::Deref;
+
+use function::{CallConv, Function, FnPointer, HookableWith};
+use super::{Hook, HookResult};
+
+
+
+/// A hook on a C-variadic function, such as `printf`.
+///
+/// Variadic functions can not be fully typed, so `T` is the type of the target function with
+/// only its fixed arguments, for example `extern "C" fn(*const c_char) -> c_int` for `printf`.
+/// The detour function receives the fixed arguments. The variadic arguments are left in place
+/// by the caller, so they are only accessible through the original function.
+///
+/// Calling the trampoline through `call_real()` passes no variadic arguments. In order to pass
+/// them on, the raw trampoline can be transmuted to the full variadic function type, such as
+/// `extern "C" fn(*const c_char, ...) -> c_int`, and called with the arguments the detour knows
+/// about, for example after parsing a format string.
+pub struct VariadicHook<T: Function>(Hook<T>);
+
+impl<T: Function> VariadicHook<T> {
+    /// Create a new hook given the address of a variadic target function and a detour function
+    /// taking only its fixed arguments.
+    ///
+    /// The hook is disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` does not use the `extern "C"` or `extern "cdecl"` calling convention. Only
+    /// with these conventions the caller cleans up the arguments, which makes it safe for a
+    /// detour function to ignore the variadic arguments.
+    ///
+    /// # Safety
+    ///
+    /// The target address must point to a variadic function whose fixed arguments match `T`.
+    /// See also the documentation for [`Hook::create_at()`](struct.Hook.html#method.create_at).
+    pub unsafe fn create<D>(target: FnPointer, detour: D) -> HookResult<VariadicHook<T>>
+    where T: HookableWith<D>, D: Function {
+        assert!(T::CONVENTION == CallConv::C || T::CONVENTION == CallConv::Cdecl,
+                "variadic functions must use the C calling convention");
+        Hook::create_at(target, detour).map(VariadicHook)
+    }
+
+    /// Returns the untyped trampoline function, which can be transmuted to the full variadic
+    /// function type.
+    pub fn raw_trampoline(&self) -> FnPointer {
+        self.0.trampoline().to_ptr()
+    }
+
+    /// Returns the underlying hook.
+    pub fn into_inner(self) -> Hook<T> {
+        self.0
+    }
+}
+
+impl<T: Function> Deref for VariadicHook<T> {
+    type Target = Hook<T>;
+
+    fn deref(&self) -> &Hook<T> {
+        &self.0
+    }
+}